@@ -1,6 +1,12 @@
-use clap::{Args, Parser, Subcommand};
+use std::str::FromStr;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use nix::sys::signal::Signal;
 use serde::{Deserialize, Serialize};
 
+use super::image::ConfigChange;
+use super::metas::{ContainerStatus, MountPoint, MountType, RestartPolicy};
+
 #[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[command(name = "rtain")]
 #[command(about = "rtain is a simple container runtime implemented in Rust.")]
@@ -9,6 +15,8 @@ pub struct CLI {
     pub command: Commands,
 }
 
+// Parsed once per invocation, not worth boxing the arguments.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
 pub enum Commands {
     /// Running a container from images.
@@ -19,18 +27,56 @@ pub enum Commands {
     Exec(ExecArgs),
     /// Stop a container.
     Stop(StopArgs),
+    /// Pause all processes of a container.
+    Pause(PauseArgs),
+    /// Resume a paused container.
+    Unpause(UnpauseArgs),
+    /// Send a signal to a running container.
+    Kill(KillArgs),
     /// Remove a stopped container.
     RM(RMArgs),
     /// List containers.
     PS(PSArgs),
+    /// Show the full record of a container.
+    Inspect(InspectArgs),
     /// Show a container's log.
     Logs(LogsArgs),
     /// Commit a container to an image.
     Commit(CommitArgs),
+    /// List images.
+    Images,
+    /// Remove images, or references to them.
+    Rmi(RmiArgs),
+    /// Add a reference to an image.
+    Tag(TagArgs),
+    /// Import a rootfs tarball as an image.
+    Import(ImportArgs),
+    /// Update resource limits of a container.
+    Update(UpdateArgs),
+
+    /// Container commands.
+    #[command(subcommand)]
+    Container(ContainerCommands),
+
+    /// Image commands.
+    #[command(subcommand)]
+    Image(ImageCommands),
 
     /// Network commands.
     #[command(subcommand)]
     Network(NetworkCommands),
+
+    /// Label commands.
+    #[command(subcommand)]
+    Label(LabelCommands),
+
+    /// Volume commands.
+    #[command(subcommand)]
+    Volume(VolumeCommands),
+
+    /// System commands.
+    #[command(subcommand)]
+    System(SystemCommands),
 }
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
@@ -39,34 +85,145 @@ pub struct RunArgs {
     #[arg(short, long)]
     pub name: Option<String>,
 
-    /// Memory limit for the container.
-    #[arg(short, long, value_parser(parse_memory_size))]
-    pub memory: Option<i64>,
+    /// Resource limits of the container.
+    #[command(flatten)]
+    pub resources: ResourceArgs,
 
-    /// Stabilize using the volume mount.
-    #[arg(short, long)]
-    pub volume: Option<String>,
+    /// Environment, working directory and user of the command.
+    #[command(flatten)]
+    pub process: ProcessArgs,
+
+    /// Set a label on the container, as key=value.
+    #[arg(short, long = "label", value_parser(parse_label))]
+    pub labels: Vec<(String, String)>,
+
+    /// Read labels from a file, one key=value per line.
+    #[arg(long, value_parser(parse_file_path))]
+    pub label_file: Vec<String>,
+
+    /// Mount a host directory as host:container[:ro], a named volume as name:container[:ro], or an
+    /// anonymous volume at a container path.
+    #[arg(short, long = "volume", value_parser(parse_volume))]
+    pub volumes: Vec<MountPoint>,
+
+    /// Mount as type=bind|volume|tmpfs,src=<source>,dst=<path>[,ro][,size=<size>].
+    #[arg(long = "mount", value_parser(parse_mount))]
+    pub mounts: Vec<MountPoint>,
 
     /// Detach the container.
     #[arg(short, long)]
     pub detach: bool,
 
-    /// Image to run.
+    /// Connect the container to a network.
+    #[arg(long)]
+    pub net: Option<String>,
+
+    /// Publish a container port to the host, as host:container.
+    #[arg(short, long = "publish", value_parser(parse_port_mapping))]
+    pub publish: Vec<(u16, u16)>,
+
+    /// Publish the TCP ports the image exposes, each to a free host port.
+    #[arg(short = 'P', long)]
+    pub publish_all: bool,
+
+    /// Restart policy, one of no, on-failure[:max-retries], always and unless-stopped.
+    #[arg(long, default_value = "no")]
+    pub restart: RestartPolicy,
+
+    /// Health check of the container.
+    #[command(flatten)]
+    pub health: HealthArgs,
+
+    /// Program to run instead of the image entrypoint, an empty one to run the command as is.
+    #[arg(long)]
+    pub entrypoint: Option<String>,
+
+    /// Image to run, by reference or ID.
     #[arg(required = true)]
     pub image: String,
 
-    /// Command to run in the container.
-    #[arg(allow_hyphen_values = true, required = true)]
+    /// Command to run in the container, or args of the entrypoint, defaults to the image cmd.
+    #[arg(allow_hyphen_values = true)]
     pub command: Vec<String>,
 }
 
+#[derive(Args, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ResourceArgs {
+    /// Memory limit for the container.
+    #[arg(short, long, value_parser(parse_memory_size))]
+    pub memory: Option<i64>,
+
+    /// Memory plus swap limit for the container, must not be less than `--memory`.
+    #[arg(long, value_parser(parse_memory_size))]
+    pub memory_swap: Option<i64>,
+
+    /// Number of CPUs the container can use, e.g. `1.5`.
+    #[arg(long)]
+    pub cpus: Option<f64>,
+
+    /// Maximum number of processes in the container.
+    #[arg(long)]
+    pub pids_limit: Option<u64>,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProcessArgs {
+    /// Set an environment variable as KEY=VALUE, or KEY to pass it from the current environment.
+    #[arg(short, long, value_parser(parse_env))]
+    pub env: Vec<(String, String)>,
+
+    /// Read environment variables from a file, one KEY=VALUE per line.
+    #[arg(long, value_parser(parse_file_path))]
+    pub env_file: Vec<String>,
+
+    /// Working directory of the command in the container.
+    #[arg(short, long)]
+    pub workdir: Option<String>,
+
+    /// User to run the command as, as user[:group], by name or ID.
+    #[arg(short, long)]
+    pub user: Option<String>,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct HealthArgs {
+    /// Command to check the container health, run with `/bin/sh -c`.
+    #[arg(long)]
+    pub health_cmd: Option<String>,
+
+    /// Time between health checks, e.g. `30s`, `1m`.
+    #[arg(long, value_parser(parse_duration), default_value = "30s")]
+    pub health_interval: u64,
+
+    /// Failures in a row to report the container unhealthy.
+    #[arg(long, default_value_t = 3)]
+    pub health_retries: u32,
+
+    /// Time for the container to start, during which failed checks are not counted.
+    #[arg(long, value_parser(parse_duration), default_value = "0s")]
+    pub health_start_period: u64,
+}
+
+impl ResourceArgs {
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none()
+            && self.memory_swap.is_none()
+            && self.cpus.is_none()
+            && self.pids_limit.is_none()
+    }
+}
+
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
 pub struct StartArgs {
-    /// Name of the container.
-    #[arg(required = true)]
-    pub name: String,
+    /// Names of the containers.
+    #[arg(required_unless_present = "filters")]
+    pub names: Vec<String>,
 
-    /// Detach the container.
+    /// Select containers by `status=<status>`, `label=<key>=<value>` or `name=<name>` too.
+    #[arg(long = "filter", value_parser(parse_filter))]
+    pub filters: Vec<(String, String)>,
+
+    /// Detach the container, always the case for more than one container.
     #[arg(short, long)]
     pub detach: bool,
 }
@@ -77,6 +234,11 @@ pub struct ExecArgs {
     #[arg(short, long)]
     pub name: String,
 
+    /// Environment, working directory and user of the command, defaults to those of the
+    /// container.
+    #[command(flatten)]
+    pub process: ProcessArgs,
+
     /// Command to run in the container.
     #[arg(allow_hyphen_values = true, required = true)]
     pub command: Vec<String>,
@@ -84,18 +246,118 @@ pub struct ExecArgs {
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
 pub struct StopArgs {
+    /// Names of the containers.
+    #[arg(required_unless_present = "filters")]
+    pub names: Vec<String>,
+
+    /// Select containers by `status=<status>`, `label=<key>=<value>` or `name=<name>` too.
+    #[arg(long = "filter", value_parser(parse_filter))]
+    pub filters: Vec<(String, String)>,
+
+    /// Seconds to wait for the container to exit before killing it.
+    #[arg(short, long, default_value_t = 10)]
+    pub time: u64,
+
+    /// Signal sent to the container to stop it.
+    #[arg(short, long, default_value = "SIGTERM", value_parser(parse_signal))]
+    pub signal: i32,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct InspectArgs {
+    /// Name or ID of the container.
+    #[arg(required = true)]
     pub name: String,
+
+    /// Format the output with a template, e.g. `{{.State.Status}}` or `{{json .Network}}`.
+    #[arg(short, long)]
+    pub format: Option<String>,
 }
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
-pub struct RMArgs {
+pub struct PauseArgs {
+    /// Name of the container.
+    #[arg(required = true)]
     pub name: String,
 }
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct UnpauseArgs {
+    /// Name of the container.
+    #[arg(required = true)]
+    pub name: String,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct KillArgs {
+    /// Names of the containers.
+    #[arg(required_unless_present = "filters")]
+    pub names: Vec<String>,
+
+    /// Select containers by `status=<status>`, `label=<key>=<value>` or `name=<name>` too.
+    #[arg(long = "filter", value_parser(parse_filter))]
+    pub filters: Vec<(String, String)>,
+
+    /// Signal to send.
+    #[arg(short, long, default_value = "SIGKILL", value_parser(parse_signal))]
+    pub signal: i32,
+
+    /// Send the signal to every process in the container, not only the init process.
+    #[arg(short, long)]
+    pub all: bool,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct RMArgs {
+    /// Names of the containers.
+    #[arg(required_unless_present = "filters")]
+    pub names: Vec<String>,
+
+    /// Select containers by `status=<status>`, `label=<key>=<value>` or `name=<name>` too.
+    #[arg(long = "filter", value_parser(parse_filter))]
+    pub filters: Vec<(String, String)>,
+
+    /// Kill running containers before removing them.
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Remove the anonymous volumes of the containers too.
+    #[arg(short, long)]
+    pub volumes: bool,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone, Default)]
 pub struct PSArgs {
+    /// Show all containers, exited ones included.
     #[arg(short, long)]
     pub all: bool,
+
+    /// Filter containers by `status=<status>`, `label=<key>=<value>` or `name=<name>`.
+    #[arg(short, long = "filter", value_parser(parse_filter))]
+    pub filters: Vec<(String, String)>,
+
+    /// Show containers created after the given container, by name or ID, exited ones included.
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Show the last N created containers, exited ones included.
+    #[arg(short = 'n', long)]
+    pub last: Option<usize>,
+
+    /// Only show container IDs.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = PSFormat::Table)]
+    pub format: PSFormat,
+}
+
+#[derive(ValueEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum PSFormat {
+    #[default]
+    Table,
+    Json,
 }
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
@@ -105,15 +367,199 @@ pub struct LogsArgs {
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
 pub struct CommitArgs {
+    /// Author of the image.
+    #[arg(short, long)]
+    pub author: Option<String>,
+
+    /// Message describing the commit.
+    #[arg(short, long)]
+    pub message: Option<String>,
+
+    /// Apply a Dockerfile instruction to the image config, one of CMD, ENTRYPOINT, ENV, EXPOSE,
+    /// USER and WORKDIR.
+    #[arg(short, long = "change", value_parser(parse_change))]
+    pub changes: Vec<ConfigChange>,
+
     /// Name of the container to commit.
     pub name: String,
-    /// Committed image name.
+    /// Reference of the committed image, as name[:tag].
+    #[arg(value_parser(parse_image_ref))]
     pub image: String,
 }
 
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct RmiArgs {
+    /// Images to remove, by reference or ID.
+    #[arg(required = true)]
+    pub names: Vec<String>,
+
+    /// Remove images used by containers, or with several references, too.
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct TagArgs {
+    /// Image to tag, by reference or ID.
+    pub source: String,
+
+    /// New reference, as name[:tag].
+    #[arg(value_parser(parse_image_ref))]
+    pub target: String,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct ImportArgs {
+    /// Rootfs tarball to import.
+    #[arg(value_parser(parse_file_path))]
+    pub path: String,
+
+    /// Reference of the image, as name[:tag].
+    #[arg(value_parser(parse_image_ref))]
+    pub reference: Option<String>,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct UpdateArgs {
+    /// Name of the container to update.
+    pub name: String,
+
+    /// New resource limits, limits not given are kept.
+    #[command(flatten)]
+    pub resources: ResourceArgs,
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
+pub enum ImageCommands {
+    /// Load images from an OCI layout or a `docker save` archive.
+    Load(ImageLoadArgs),
+    /// Save images as an OCI layout.
+    Save(ImageSaveArgs),
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct ImageLoadArgs {
+    /// OCI layout directory, or tarball of an OCI layout or from `docker save`.
+    #[arg(short, long, value_parser(parse_input_path))]
+    pub input: String,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct ImageSaveArgs {
+    /// Images to save, by reference or ID.
+    #[arg(required = true)]
+    pub names: Vec<String>,
+
+    /// Directory to write the layout to, or tarball if ending with `.tar`.
+    #[arg(short, long, value_parser(parse_output_path))]
+    pub output: String,
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
+pub enum ContainerCommands {
+    /// Remove all stopped containers.
+    Prune(PruneArgs),
+}
+
 #[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
 pub enum NetworkCommands {
+    /// Create a network.
     Create(NetCreateArgs),
+    /// List networks.
+    Ls,
+    /// Show a network and its attached containers.
+    Inspect(NetNameArgs),
+    /// Remove a network without attached containers.
+    Rm(NetNameArgs),
+    /// Connect a container to a network.
+    Connect(NetConnectArgs),
+    /// Disconnect a container from a network.
+    Disconnect(NetConnectArgs),
+    /// Remove all networks without attached containers.
+    Prune(PruneArgs),
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
+pub enum LabelCommands {
+    /// Add labels to a container, replacing those with the same keys.
+    Add(LabelAddArgs),
+    /// Remove labels from a container.
+    Rm(LabelRmArgs),
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct LabelAddArgs {
+    /// Name of the container.
+    #[arg(required = true)]
+    pub name: String,
+
+    /// Labels to add, as key=value.
+    #[arg(required = true, value_parser(parse_label))]
+    pub labels: Vec<(String, String)>,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct LabelRmArgs {
+    /// Name of the container.
+    #[arg(required = true)]
+    pub name: String,
+
+    /// Keys of the labels to remove.
+    #[arg(required = true)]
+    pub keys: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
+pub enum VolumeCommands {
+    /// Create a volume.
+    Create(VolumeCreateArgs),
+    /// List volumes.
+    Ls,
+    /// Show a volume as JSON.
+    Inspect(VolumeNameArgs),
+    /// Remove volumes not used by any container.
+    Rm(VolumeRmArgs),
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct VolumeCreateArgs {
+    /// Name of the volume, generated if not given.
+    #[arg(value_parser(parse_volume_name))]
+    pub name: Option<String>,
+
+    /// Driver of the volume, currently only `local` supported.
+    #[arg(short, long, default_value = "local")]
+    pub driver: String,
+
+    /// Set a label on the volume, as key=value.
+    #[arg(short, long = "label", value_parser(parse_label))]
+    pub labels: Vec<(String, String)>,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct VolumeNameArgs {
+    /// Name of the volume.
+    pub name: String,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct VolumeRmArgs {
+    /// Names of the volumes.
+    #[arg(required = true)]
+    pub names: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone)]
+pub enum SystemCommands {
+    /// Remove stopped containers, unused networks and what removed containers left behind.
+    Prune(PruneArgs),
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct PruneArgs {
+    /// Only remove what matches, as until=<duration> (created longer ago) or label=key=value.
+    #[arg(long = "filter", value_parser(parse_prune_filter))]
+    pub filters: Vec<(String, String)>,
 }
 
 #[derive(Args, Debug, Serialize, Deserialize, Clone)]
@@ -131,6 +577,24 @@ pub struct NetCreateArgs {
     pub name: String,
 }
 
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct NetNameArgs {
+    /// Network name.
+    #[arg(required = true)]
+    pub name: String,
+}
+
+#[derive(Args, Debug, Serialize, Deserialize, Clone)]
+pub struct NetConnectArgs {
+    /// Network name.
+    #[arg(required = true)]
+    pub network: String,
+
+    /// Container name.
+    #[arg(required = true)]
+    pub container: String,
+}
+
 /// Parse a memory size string into bytes.
 fn parse_memory_size(input: &str) -> Result<i64, String> {
     let input = input.trim().to_lowercase();
@@ -155,6 +619,348 @@ fn parse_memory_size(input: &str) -> Result<i64, String> {
     Ok(number * multiplier)
 }
 
+/// Parse a duration string into seconds.
+fn parse_duration(input: &str) -> Result<u64, String> {
+    let input = input.trim().to_lowercase();
+
+    let (number, multiplier): (&str, u64) = if input.ends_with("h") {
+        (&input[..input.len() - 1], 60 * 60)
+    } else if input.ends_with("m") {
+        (&input[..input.len() - 1], 60)
+    } else if input.ends_with("s") {
+        (&input[..input.len() - 1], 1)
+    } else {
+        (input.as_str(), 1) // default is seconds
+    };
+
+    let number: u64 = match number.parse() {
+        Ok(n) => n,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or(format!("duration {input} is too long"))
+}
+
+/// Parse a signal given by name, with or without the `SIG` prefix, or by number.
+fn parse_signal(input: &str) -> Result<i32, String> {
+    let input = input.trim().to_uppercase();
+
+    let signal = match input.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).map_err(|e| e.to_string())?,
+        Err(_) if input.starts_with("SIG") => {
+            Signal::from_str(&input).map_err(|e| e.to_string())?
+        }
+        Err(_) => Signal::from_str(&format!("SIG{input}")).map_err(|e| e.to_string())?,
+    };
+
+    Ok(signal as i32)
+}
+
+/// Parse an environment variable `KEY=VALUE`, a lone `KEY` takes the value from the current
+/// environment.
+fn parse_env(input: &str) -> Result<(String, String), String> {
+    let (key, value) = match input.split_once('=') {
+        Some((key, value)) => (key.to_string(), value.to_string()),
+        None => {
+            let value = std::env::var(input)
+                .map_err(|_| format!("Environment variable {input} is not set"))?;
+            (input.to_string(), value)
+        }
+    };
+
+    if key.is_empty() {
+        return Err("Environment variable name must not be empty".to_string());
+    }
+
+    Ok((key, value))
+}
+
+/// Check a file exists, and make its path absolute for the daemon to read.
+fn parse_file_path(input: &str) -> Result<String, String> {
+    let path = std::fs::canonicalize(input).map_err(|e| format!("{input}: {e}"))?;
+    if !path.is_file() {
+        return Err(format!("{input} is not a file"));
+    }
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Check a file or directory exists, and make its path absolute for the daemon to read.
+fn parse_input_path(input: &str) -> Result<String, String> {
+    let path = std::fs::canonicalize(input).map_err(|e| format!("{input}: {e}"))?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Make a path absolute for the daemon to write, it must not exist yet.
+fn parse_output_path(input: &str) -> Result<String, String> {
+    let path = std::path::absolute(input).map_err(|e| format!("{input}: {e}"))?;
+    if path.exists() {
+        return Err(format!("{input} already exists"));
+    }
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Parse a label `key=value`, a lone `key` has an empty value.
+fn parse_label(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input.split_once('=').unwrap_or((input, ""));
+    if key.is_empty() {
+        return Err("Label key must not be empty".to_string());
+    }
+
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse a container filter `key=value`.
+fn parse_filter(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input.split_once('=').ok_or("Filter must be key=value")?;
+
+    match key {
+        "status" => {
+            ContainerStatus::from_str(value)?;
+        }
+        "label" => {
+            if !value.contains('=') {
+                return Err("Label filter must be label=key=value".to_string());
+            }
+        }
+        "name" => {}
+        _ => return Err(format!("Invalid filter: {key}")),
+    }
+
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse a `key=value` filter of prune, the duration of `until` is turned into seconds.
+fn parse_prune_filter(input: &str) -> Result<(String, String), String> {
+    let (key, value) = input.split_once('=').ok_or("Filter must be key=value")?;
+
+    match key {
+        "until" => Ok((key.to_string(), parse_duration(value)?.to_string())),
+        "label" if value.contains('=') => Ok((key.to_string(), value.to_string())),
+        "label" => Err("Label filter must be label=key=value".to_string()),
+        _ => Err(format!("Invalid filter: {key}")),
+    }
+}
+
+/// Parse a volume `[source:]container[:ro|rw]`, where an absolute source is a host directory and
+/// any other a volume name. Without a source, the volume is anonymous.
+fn parse_volume(input: &str) -> Result<MountPoint, String> {
+    let parts: Vec<_> = input.split(':').collect();
+
+    let (source, destination, read_only) = match parts.as_slice() {
+        [destination] => ("", *destination, false),
+        [source, destination] => (*source, *destination, false),
+        [source, destination, "ro"] => (*source, *destination, true),
+        [source, destination, "rw"] => (*source, *destination, false),
+        [_, _, mode] => return Err(format!("Invalid volume mode: {mode}")),
+        _ => return Err("Volume must be [source:]container[:ro|rw]".to_string()),
+    };
+
+    let mount_type = if source.starts_with('/') {
+        MountType::Bind
+    } else {
+        MountType::Volume
+    };
+    if parts.len() > 1 && source.is_empty() {
+        return Err("Volume source must not be empty".to_string());
+    }
+
+    check_mount(MountPoint {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        mount_type,
+        read_only,
+        size: None,
+    })
+}
+
+/// Parse a mount `type=bind|volume|tmpfs,src=<source>,dst=<path>[,ro][,size=<size>]`, of type
+/// volume if not given.
+fn parse_mount(input: &str) -> Result<MountPoint, String> {
+    let mut mount = MountPoint {
+        source: String::new(),
+        destination: String::new(),
+        mount_type: MountType::Volume,
+        read_only: false,
+        size: None,
+    };
+
+    for option in input.split(',') {
+        let (key, value) = match option.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (option, None),
+        };
+
+        match (key, value) {
+            ("type", Some("bind")) => mount.mount_type = MountType::Bind,
+            ("type", Some("volume")) => mount.mount_type = MountType::Volume,
+            ("type", Some("tmpfs")) => mount.mount_type = MountType::Tmpfs,
+            ("source" | "src", Some(source)) => mount.source = source.to_string(),
+            ("destination" | "dst" | "target", Some(destination)) => {
+                mount.destination = destination.to_string()
+            }
+            ("readonly" | "ro", None | Some("true" | "1")) => mount.read_only = true,
+            ("readonly" | "ro", Some("false" | "0")) => mount.read_only = false,
+            ("size", Some(size)) => mount.size = Some(parse_memory_size(size)? as u64),
+            _ => return Err(format!("Invalid mount option: {option}")),
+        }
+    }
+
+    match mount.mount_type {
+        MountType::Bind if !mount.source.starts_with('/') => {
+            return Err("Bind mount source must be an absolute path".to_string())
+        }
+        MountType::Tmpfs if !mount.source.is_empty() => {
+            return Err("Tmpfs mount takes no source".to_string())
+        }
+        _ => {}
+    }
+    if mount.size.is_some() && mount.mount_type != MountType::Tmpfs {
+        return Err("Only tmpfs mounts take a size".to_string());
+    }
+
+    check_mount(mount)
+}
+
+fn check_mount(mount: MountPoint) -> Result<MountPoint, String> {
+    if !mount.destination.starts_with('/') {
+        return Err(format!(
+            "Mount destination {} must be an absolute path",
+            mount.destination
+        ));
+    }
+    if std::path::Path::new(&mount.destination)
+        .components()
+        .any(|component| component == std::path::Component::ParentDir)
+    {
+        return Err(format!(
+            "Mount destination {} must not contain `..`",
+            mount.destination
+        ));
+    }
+    if mount.mount_type == MountType::Volume && !mount.source.is_empty() {
+        parse_volume_name(&mount.source)?;
+    }
+
+    Ok(mount)
+}
+
+/// Parse a volume name, made of letters, digits and `_.-`, starting with a letter or digit.
+fn parse_volume_name(input: &str) -> Result<String, String> {
+    let valid = input.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && input
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    if !valid {
+        return Err(format!("Invalid volume name: {input}"));
+    }
+
+    Ok(input.to_string())
+}
+
+/// Parse an image reference `name[:tag]`, where the name is made of lowercase components split by
+/// `/`. The tag defaults to `latest`.
+pub(crate) fn parse_image_ref(input: &str) -> Result<String, String> {
+    let (name, tag) = match input.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name, tag),
+        _ => (input, "latest"),
+    };
+
+    let valid_name = name.split('/').all(|part| {
+        part.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_.-".contains(c))
+    });
+    if !valid_name {
+        return Err(format!("Invalid image name: {name}"));
+    }
+
+    let valid_tag = tag.len() <= 128
+        && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    if !valid_tag {
+        return Err(format!("Invalid image tag: {tag}"));
+    }
+
+    Ok(format!("{name}:{tag}"))
+}
+
+/// Parse a Dockerfile instruction changing the config of a committed image. Commands are given as
+/// a JSON array, or as a line run with `/bin/sh -c`.
+fn parse_change(input: &str) -> Result<ConfigChange, String> {
+    let (instruction, value) = input
+        .trim()
+        .split_once(char::is_whitespace)
+        .map(|(instruction, value)| (instruction, value.trim()))
+        .ok_or(format!(
+            "Change must be an instruction and its value: {input}"
+        ))?;
+
+    let command = |value: &str| -> Result<Vec<String>, String> {
+        if value.starts_with('[') {
+            serde_json::from_str(value).map_err(|e| format!("Invalid command {value}: {e}"))
+        } else {
+            Ok(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                value.to_string(),
+            ])
+        }
+    };
+
+    match instruction.to_uppercase().as_str() {
+        "CMD" => Ok(ConfigChange::Cmd(command(value)?)),
+        "ENTRYPOINT" => Ok(ConfigChange::Entrypoint(command(value)?)),
+        "ENV" => {
+            // As `KEY=VALUE`, or `KEY VALUE` as Dockerfiles also allow.
+            let (key, value) = match value.split_once(char::is_whitespace) {
+                Some((key, value)) if !key.contains('=') => Some((key, value)),
+                _ => value.split_once('='),
+            }
+            .ok_or(format!("ENV must be KEY=VALUE: {value}"))?;
+            if key.is_empty() {
+                return Err("Environment variable name must not be empty".to_string());
+            }
+
+            Ok(ConfigChange::Env(key.to_string(), value.trim().to_string()))
+        }
+        "EXPOSE" => {
+            let (port, protocol) = value.split_once('/').unwrap_or((value, "tcp"));
+            match (port.parse::<u16>(), protocol) {
+                (Ok(port), "tcp" | "udp") if port != 0 => {
+                    Ok(ConfigChange::Expose(format!("{port}/{protocol}")))
+                }
+                _ => Err(format!("Invalid port: {value}")),
+            }
+        }
+        "USER" => Ok(ConfigChange::User(value.to_string())),
+        "WORKDIR" if value.starts_with('/') => Ok(ConfigChange::Workdir(value.to_string())),
+        "WORKDIR" => Err(format!("Working directory {value} must be absolute")),
+        _ => Err(format!("Unsupported instruction: {instruction}")),
+    }
+}
+
+/// Parse a port mapping `host:container`.
+fn parse_port_mapping(input: &str) -> Result<(u16, u16), String> {
+    let (host, container) = input
+        .split_once(':')
+        .ok_or("Port mapping must be host:container")?;
+
+    let parse_port = |port: &str| match port.trim().parse::<u16>() {
+        Ok(0) | Err(_) => Err(format!("Invalid port: {port}")),
+        Ok(port) => Ok(port),
+    };
+
+    Ok((parse_port(host)?, parse_port(container)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,4 +986,206 @@ mod tests {
         assert_eq!(parse_memory_size("0").unwrap(), 0);
         assert_eq!(parse_memory_size("  100m  ").unwrap(), 100 * 1024 * 1024);
     }
+
+    #[test]
+    fn test_parse_port_mapping() {
+        assert_eq!(parse_port_mapping("8080:80").unwrap(), (8080, 80));
+        assert!(parse_port_mapping("8080").is_err());
+        assert!(parse_port_mapping("0:80").is_err());
+        assert!(parse_port_mapping("8080:70000").is_err());
+        assert!(parse_port_mapping("a:80").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30").unwrap(), 30);
+        assert_eq!(parse_duration("30s").unwrap(), 30);
+        assert_eq!(parse_duration("2m").unwrap(), 120);
+        assert_eq!(parse_duration("1h").unwrap(), 3600);
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("99999999999999999h").is_err());
+    }
+
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env("A=1=2").unwrap(),
+            ("A".to_string(), "1=2".to_string())
+        );
+        assert_eq!(parse_env("A=").unwrap(), ("A".to_string(), String::new()));
+        assert!(parse_env("=1").is_err());
+        assert!(parse_env("RTAIN_SURELY_UNSET").is_err());
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            parse_label("owner=ops").unwrap(),
+            ("owner".to_string(), "ops".to_string())
+        );
+        assert_eq!(
+            parse_label("stable").unwrap(),
+            ("stable".to_string(), String::new())
+        );
+        assert!(parse_label("=ops").is_err());
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(
+            parse_filter("status=running").unwrap(),
+            ("status".to_string(), "running".to_string())
+        );
+        assert_eq!(
+            parse_filter("label=app=web").unwrap(),
+            ("label".to_string(), "app=web".to_string())
+        );
+        assert!(parse_filter("status=stopped").is_err());
+        assert!(parse_filter("label=app").is_err());
+        assert!(parse_filter("image=nginx").is_err());
+        assert!(parse_filter("name").is_err());
+    }
+
+    #[test]
+    fn test_parse_prune_filter() {
+        assert_eq!(
+            parse_prune_filter("until=24h").unwrap(),
+            ("until".to_string(), "86400".to_string())
+        );
+        assert_eq!(
+            parse_prune_filter("label=app=web").unwrap(),
+            ("label".to_string(), "app=web".to_string())
+        );
+        assert!(parse_prune_filter("until=yesterday").is_err());
+        assert!(parse_prune_filter("label=app").is_err());
+        assert!(parse_prune_filter("status=exited").is_err());
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("SIGTERM").unwrap(), 15);
+        assert_eq!(parse_signal("kill").unwrap(), 9);
+        assert_eq!(parse_signal("2").unwrap(), 2);
+        assert!(parse_signal("SIGFOO").is_err());
+        assert!(parse_signal("0").is_err());
+    }
+
+    #[test]
+    fn test_parse_volume() {
+        let mount = parse_volume("/host/data:/data:ro").unwrap();
+        assert_eq!(mount.mount_type, MountType::Bind);
+        assert_eq!(
+            (mount.source.as_str(), mount.destination.as_str()),
+            ("/host/data", "/data")
+        );
+        assert!(mount.read_only);
+
+        let mount = parse_volume("cache:/var/cache").unwrap();
+        assert_eq!(mount.mount_type, MountType::Volume);
+        assert_eq!(mount.source, "cache");
+        assert!(!mount.read_only);
+
+        let mount = parse_volume("/data").unwrap();
+        assert_eq!(mount.mount_type, MountType::Volume);
+        assert!(mount.source.is_empty());
+
+        assert!(parse_volume("/host:data").is_err());
+        assert!(parse_volume(":/data").is_err());
+        assert!(parse_volume("/host:/data:rx").is_err());
+        assert!(parse_volume("/a:/b:ro:rw").is_err());
+        assert!(parse_volume("../etc:/data").is_err());
+        assert!(parse_volume("/x:/../../etc").is_err());
+        assert!(parse_mount("type=tmpfs,dst=/data/../../etc").is_err());
+    }
+
+    #[test]
+    fn test_parse_volume_name() {
+        assert_eq!(parse_volume_name("db-data_1.0").unwrap(), "db-data_1.0");
+        assert!(parse_volume_name("").is_err());
+        assert!(parse_volume_name(".hidden").is_err());
+        assert!(parse_volume_name("a/b").is_err());
+    }
+
+    #[test]
+    fn test_parse_image_ref() {
+        assert_eq!(parse_image_ref("busybox").unwrap(), "busybox:latest");
+        assert_eq!(
+            parse_image_ref("library/app:1.0").unwrap(),
+            "library/app:1.0"
+        );
+        assert_eq!(parse_image_ref("my-app:v2_rc.1").unwrap(), "my-app:v2_rc.1");
+
+        assert!(parse_image_ref("").is_err());
+        assert!(parse_image_ref("App").is_err());
+        assert!(parse_image_ref("app:").is_err());
+        assert!(parse_image_ref("app:.x").is_err());
+        assert!(parse_image_ref("/app").is_err());
+        assert!(parse_image_ref("app//web").is_err());
+    }
+
+    #[test]
+    fn test_parse_change() {
+        assert_eq!(
+            parse_change(r#"CMD ["nginx", "-g", "daemon off;"]"#).unwrap(),
+            ConfigChange::Cmd(vec![
+                "nginx".to_string(),
+                "-g".to_string(),
+                "daemon off;".to_string()
+            ])
+        );
+        assert_eq!(
+            parse_change("entrypoint echo hi").unwrap(),
+            ConfigChange::Entrypoint(vec![
+                "/bin/sh".to_string(),
+                "-c".to_string(),
+                "echo hi".to_string()
+            ])
+        );
+        assert_eq!(
+            parse_change("ENV LANG=C.UTF-8").unwrap(),
+            ConfigChange::Env("LANG".to_string(), "C.UTF-8".to_string())
+        );
+        assert_eq!(
+            parse_change("ENV LANG C").unwrap(),
+            ConfigChange::Env("LANG".to_string(), "C".to_string())
+        );
+        assert_eq!(
+            parse_change("EXPOSE 80").unwrap(),
+            ConfigChange::Expose("80/tcp".to_string())
+        );
+        assert_eq!(
+            parse_change("WORKDIR /srv").unwrap(),
+            ConfigChange::Workdir("/srv".to_string())
+        );
+
+        assert!(parse_change("CMD").is_err());
+        assert!(parse_change("CMD [\"sh\"").is_err());
+        assert!(parse_change("EXPOSE 80/sctp").is_err());
+        assert!(parse_change("WORKDIR srv").is_err());
+        assert!(parse_change("RUN make").is_err());
+    }
+
+    #[test]
+    fn test_parse_mount() {
+        let mount = parse_mount("type=tmpfs,dst=/run,size=64m").unwrap();
+        assert_eq!(mount.mount_type, MountType::Tmpfs);
+        assert_eq!(mount.destination, "/run");
+        assert_eq!(mount.size, Some(64 * 1024 * 1024));
+
+        let mount = parse_mount("type=bind,source=/etc/app,target=/etc/app,readonly").unwrap();
+        assert_eq!(mount.mount_type, MountType::Bind);
+        assert_eq!(mount.source, "/etc/app");
+        assert!(mount.read_only);
+
+        let mount = parse_mount("dst=/data").unwrap();
+        assert_eq!(mount.mount_type, MountType::Volume);
+        assert!(mount.source.is_empty());
+
+        assert!(parse_mount("type=bind,src=app,dst=/app").is_err());
+        assert!(parse_mount("type=tmpfs,src=/tmp,dst=/tmp").is_err());
+        assert!(parse_mount("type=volume,dst=/data,size=1g").is_err());
+        assert!(parse_mount("type=nfs,dst=/data").is_err());
+        assert!(parse_mount("src=/data").is_err());
+    }
 }
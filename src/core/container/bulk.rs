@@ -0,0 +1,74 @@
+use std::future::Future;
+
+use futures::future::join_all;
+use log::error;
+use tokio::net::UnixStream;
+
+use crate::core::{metas::CONTAINER_METAS, Msg};
+
+use super::list::filter_from;
+
+/// Names of the containers to operate on, those given then those matching the filters.
+pub async fn select_containers(
+    names: &[String],
+    filters: &[(String, String)],
+) -> anyhow::Result<Vec<String>> {
+    let mut selected = names.to_vec();
+
+    if !filters.is_empty() {
+        let filter = filter_from(filters)?;
+        let matched = CONTAINER_METAS
+            .get()
+            .unwrap()
+            .list_containers(Some(filter))
+            .await;
+        if matched.is_empty() && names.is_empty() {
+            return Err(anyhow::anyhow!("No container matches the filters"));
+        }
+
+        for meta in matched {
+            if !selected.contains(&meta.name) {
+                selected.push(meta.name);
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Run `op` on all the `names` at once, and reply with one line per container. The reply is an
+/// error if any of them failed.
+pub async fn run_bulk<F, Fut>(names: Vec<String>, op: F, stream: &mut UnixStream)
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    let results = join_all(names.into_iter().map(op)).await;
+
+    let failed = results.iter().any(|result| result.is_err());
+    let lines: Vec<_> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(line) => line,
+            Err(e) => {
+                error!("{:?}", e);
+                e.to_string()
+            }
+        })
+        .collect();
+
+    let msg = if failed {
+        Msg::Err(lines.join("\n"))
+    } else {
+        Msg::OkContent(lines.join("\n"))
+    };
+    let _ = msg.send_to(stream).await;
+}
+
+/// Reply with the failure to select containers.
+pub async fn reply_selection_error(action: &str, e: anyhow::Error, stream: &mut UnixStream) {
+    error!("Failed to {} containers: {:?}", action, e);
+    let _ = Msg::Err(format!("Failed to {} containers: {}", action, e))
+        .send_to(stream)
+        .await;
+}
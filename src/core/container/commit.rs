@@ -1,10 +1,15 @@
-use std::{path::Path, process::Command};
+use std::path::Path;
 
 use log::error;
 use tokio::net::UnixStream;
 
+use super::pause::set_frozen;
+
 use crate::core::cmd::CommitArgs;
-use crate::core::metas::CONTAINER_METAS;
+use crate::core::image::{
+    add_image, commit_config, export_layer, find_image, staging_dir, staging_tarball,
+};
+use crate::core::metas::{ContainerMeta, ContainerStatus, CONTAINER_METAS};
 use crate::core::{Msg, ROOT_PATH};
 
 pub async fn commit_container(cm_args: CommitArgs, mut stream: UnixStream) {
@@ -32,29 +37,12 @@ pub async fn commit_container(cm_args: CommitArgs, mut stream: UnixStream) {
         }
     };
 
-    let name_id = format!("{}-{}", meta.name, meta.id);
-
-    let mnt_path = Path::new(ROOT_PATH).join(name_id).join("mnt");
-    let image_path = Path::new(&cm_args.image).join(format!("{}.tar", cm_args.image));
-
-    // Use tar command to create an image tarball
-    let output = match Command::new("tar")
-        .arg("-czf")
-        .arg(image_path)
-        .arg("-C")
-        .arg(mnt_path)
-        .arg(".")
-        .output()
-    {
-        Ok(output) => output,
+    let id = match commit(&meta, &cm_args).await {
+        Ok(id) => id,
         Err(e) => {
-            error!(
-                "Failed to commit container {}, due to: {}",
-                &cm_args.name, e
-            );
-
+            error!("Failed to commit container {}, {}", &cm_args.name, e);
             let _ = Msg::Err(format!(
-                "Failed to commit container {}, cannot tar the image: {}",
+                "Failed to commit container {}, {}",
                 cm_args.name, e
             ))
             .send_to(&mut stream)
@@ -64,26 +52,70 @@ pub async fn commit_container(cm_args: CommitArgs, mut stream: UnixStream) {
         }
     };
 
-    if !output.status.success() {
-        error!(
-            "Failed to commit container {}, tar command failed",
-            &cm_args.name
-        );
-        let error = String::from_utf8_lossy(&output.stderr);
-        let _ = Msg::Err(format!(
-            "Failed to commit container {}, tar command failed: {}",
-            cm_args.name, error
-        ))
-        .send_to(&mut stream)
-        .await;
-
-        return;
-    }
-
     let _ = Msg::OkContent(format!(
-        "Container {} commited to image {}",
-        cm_args.name, cm_args.image
+        "Container {} commited to image {} ({})",
+        cm_args.name, cm_args.image, id
     ))
     .send_to(&mut stream)
     .await;
 }
+
+/// Add the write layer of the container as a new layer on top of those of its image, making an
+/// image with the config of that one, changed as asked. Returns the image ID.
+async fn commit(meta: &ContainerMeta, cm_args: &CommitArgs) -> anyhow::Result<String> {
+    // Containers of records before images had layers have the image unpacked in their workspace.
+    if meta.layers.is_empty() {
+        return Err(anyhow::anyhow!(
+            "the container was created before images had layers, its image is unknown"
+        ));
+    }
+
+    let name_id = format!("{}-{}", meta.name, meta.id);
+    let upper = Path::new(ROOT_PATH).join(&name_id).join("writeLayer");
+    let staged = staging_tarball();
+
+    // Paused for the export, so that the layer is not taken amid writes. A container paused by
+    // hand is left so.
+    let pause = meta.state.status == ContainerStatus::Running;
+    if pause {
+        set_frozen(&name_id, true).map_err(|e| anyhow::anyhow!("cannot pause: {e}"))?;
+    }
+    let exported = export_layer(&upper, &staging_dir(), &staged);
+    if pause {
+        if let Err(e) = set_frozen(&name_id, false) {
+            error!("Failed to unpause container {}: {}", meta.name, e);
+        }
+    }
+    if let Err(e) = exported {
+        let _ = std::fs::remove_file(&staged);
+        return Err(anyhow::anyhow!("cannot export the write layer: {e}"));
+    }
+
+    // The image may be removed by now, while its layers are kept for the container.
+    let parent = find_image(&meta.image_id)
+        .await
+        .ok()
+        .and_then(|image| image.config);
+    let config = match commit_config(
+        parent.as_deref(),
+        &meta.layers,
+        &staged,
+        cm_args.author.as_deref(),
+        cm_args.message.as_deref(),
+        &cm_args.changes,
+    ) {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = std::fs::remove_file(&staged);
+            return Err(anyhow::anyhow!("cannot make the image config: {e}"));
+        }
+    };
+
+    // And have it in the image store.
+    add_image(&meta.layers, &staged, config, &cm_args.image)
+        .await
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&staged);
+            anyhow::anyhow!("cannot add the image: {e}")
+        })
+}
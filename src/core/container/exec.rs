@@ -1,10 +1,10 @@
 use std::{
-    ffi::CString,
     io::{Read, Write},
     os::{
         fd::{AsRawFd, BorrowedFd},
         unix::net::UnixStream as StdUnixStream,
     },
+    path::Path,
     process::exit,
 };
 
@@ -18,17 +18,20 @@ use nix::{
         stat::Mode,
         wait::{waitpid, WaitStatus},
     },
-    unistd::{dup2, execvp, fork, ForkResult, Pid},
+    unistd::{dup2, fork, setpgid, ForkResult, Pid},
 };
 use tokio::net::UnixStream;
 
 use crate::core::{
     cmd::ExecArgs,
-    metas::{ContainerMeta, CONTAINER_METAS},
+    metas::{ContainerMeta, ContainerStatus, CONTAINER_METAS},
     Msg,
 };
 
-use super::init::do_run;
+use super::{
+    init::do_run,
+    process::{merge_env, ProcessConfig},
+};
 
 /// Enter a container.
 pub async fn exec_container(exec_args: ExecArgs, mut stream: UnixStream) {
@@ -56,6 +59,18 @@ pub async fn exec_container(exec_args: ExecArgs, mut stream: UnixStream) {
         }
     };
 
+    if meta.state.status == ContainerStatus::Paused {
+        error!("Failed to exec container {}, it's paused", exec_args.name);
+        let _ = Msg::Err(format!(
+            "Failed to exec container {}, it's paused, unpause it first",
+            &exec_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
     if !meta.state.status.is_running() {
         error!(
             "Failed to exec container {}, it's not running",
@@ -71,7 +86,7 @@ pub async fn exec_container(exec_args: ExecArgs, mut stream: UnixStream) {
         return;
     }
 
-    let (pty, sock, child) = match exec_prepare(&meta).await {
+    let (pty, sock, child) = match exec_prepare(&meta, &exec_args).await {
         Ok(res) => res,
         Err(e) => {
             error!("Failed to start container: {:?}", e);
@@ -83,10 +98,13 @@ pub async fn exec_container(exec_args: ExecArgs, mut stream: UnixStream) {
 
     log::warn!("exec with child {child}");
 
-    do_run(meta.name, meta.id, child, pty, sock, stream, false, false).await;
+    do_run(meta.name, meta.id, child, pty, sock, Some(stream), false).await;
 }
 
-async fn exec_prepare(meta: &ContainerMeta) -> anyhow::Result<(OpenptyResult, StdUnixStream, Pid)> {
+async fn exec_prepare(
+    meta: &ContainerMeta,
+    exec_args: &ExecArgs,
+) -> anyhow::Result<(OpenptyResult, StdUnixStream, Pid)> {
     // let name_id = format!("{}-{}", &meta.name, &meta.id);
 
     let pty = openpty(None, None)?;
@@ -101,12 +119,26 @@ async fn exec_prepare(meta: &ContainerMeta) -> anyhow::Result<(OpenptyResult, St
         None => return Err(anyhow::anyhow!("Container is not running")),
     };
 
-    let child = match exec_container_process(container_pid, c_sock, &pty, &meta.command) {
-        Ok(child) => child,
-        Err(e) => {
-            return Err(e);
-        }
-    };
+    // The command inherits the settings of the container unless given.
+    let env = merge_env(&meta.env, &exec_args.process)?;
+    let process = ProcessConfig::resolve(
+        Path::new(&format!("/proc/{container_pid}/root")),
+        &env,
+        exec_args
+            .process
+            .workdir
+            .as_deref()
+            .or(meta.working_dir.as_deref()),
+        exec_args.process.user.as_deref().or(meta.user.as_deref()),
+    )?;
+
+    let child =
+        match exec_container_process(container_pid, c_sock, &pty, &exec_args.command, &process) {
+            Ok(child) => child,
+            Err(e) => {
+                return Err(e);
+            }
+        };
 
     // Wait for child ready.
     p_sock.read_exact(&mut buf).unwrap();
@@ -137,6 +169,7 @@ fn exec_container_process(
     mut c_sock: StdUnixStream,
     pty: &OpenptyResult,
     command: &Vec<String>,
+    process: &ProcessConfig,
 ) -> anyhow::Result<Pid> {
     const STACK_SIZE: usize = 1 * 1024 * 1024;
     let mut child_stack: Vec<u8> = vec![0; STACK_SIZE];
@@ -209,7 +242,7 @@ fn exec_container_process(
                             _ => unreachable!(),
                         }
 
-                        if let Err(e) = do_exec(command) {
+                        if let Err(e) = process.exec(command) {
                             error!("Failed to exec in container: {:?}", e);
                             return -1;
                         }
@@ -233,6 +266,91 @@ fn exec_container_process(
     Ok(child)
 }
 
+/// Run `command` inside the container namespaces with no terminal attached, for probing the
+/// container. The exit code of the returned process is that of the command, which runs in the
+/// process group of the returned process, so that killing the group kills both.
+pub fn exec_probe_process(
+    container: i32,
+    command: &[String],
+    process: &ProcessConfig,
+) -> anyhow::Result<Pid> {
+    const STACK_SIZE: usize = 1024 * 1024;
+    let mut child_stack: Vec<u8> = vec![0; STACK_SIZE];
+
+    let child_func = || {
+        // Forked commands inherit the group.
+        if let Err(e) = setpgid(Pid::from_raw(0), Pid::from_raw(0)) {
+            error!(
+                "Failed to probe container, cannot set process group: {:?}",
+                e
+            );
+            return -1;
+        }
+
+        // Open before entering the container, whose rootfs may have no `/dev/null`.
+        let null_fd = match open("/dev/null", OFlag::O_RDWR, Mode::empty()) {
+            Ok(fd) => fd,
+            Err(e) => {
+                error!("Failed to probe container, cannot open /dev/null: {:?}", e);
+                return -1;
+            }
+        };
+        let setup_stdio = || -> anyhow::Result<()> {
+            dup2(null_fd, nix::libc::STDIN_FILENO)?;
+            dup2(null_fd, nix::libc::STDOUT_FILENO)?;
+            dup2(null_fd, nix::libc::STDERR_FILENO)?;
+
+            Ok(())
+        };
+
+        if let Err(e) = enter_ns(container) {
+            error!("Failed to probe container, cannot enter namespace: {:?}", e);
+            return -1;
+        }
+
+        // SAFETY: Same as in `exec_container_process`, a fork is needed to join the pid namespace.
+        match unsafe { fork() } {
+            Err(e) => {
+                error!("Failed to probe container, cannot fork: {:?}", e);
+                -1
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let code = match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, code)) => code,
+                    Ok(WaitStatus::Signaled(_, sig, _)) => sig as i32,
+                    _ => -1,
+                };
+
+                exit(code)
+            }
+            Ok(ForkResult::Child) => {
+                if let Err(e) = setup_stdio() {
+                    error!("Failed to probe container, cannot redirect io: {:?}", e);
+                    return -1;
+                }
+
+                if let Err(e) = process.exec(command) {
+                    error!("Failed to probe container: {:?}", e);
+                }
+                -1
+            }
+        }
+    };
+
+    let child = unsafe {
+        clone(
+            Box::new(child_func),
+            &mut child_stack,
+            CloneFlags::empty(),
+            Some(SIGCHLD),
+        )
+    }?;
+    // Set here as well, so that the group exists by the time the probe may be killed.
+    let _ = setpgid(child, child);
+
+    Ok(child)
+}
+
 fn enter_ns(pid: i32) -> anyhow::Result<()> {
     for ns in ["ipc", "uts", "net", "pid", "mnt"] {
         let nspath = format!("/proc/{}/ns/{}", pid, ns);
@@ -246,15 +364,3 @@ fn enter_ns(pid: i32) -> anyhow::Result<()> {
 
     Ok(())
 }
-
-fn do_exec(command: &Vec<String>) -> anyhow::Result<()> {
-    let command_cstr = CString::new(command[0].clone())?;
-    let args_cstr: Vec<CString> = command
-        .iter()
-        .map(|arg| CString::new(arg.clone()).unwrap())
-        .collect();
-
-    execvp(&command_cstr, &args_cstr)?;
-
-    Ok(())
-}
@@ -0,0 +1,177 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+use nix::sys::{
+    signal::{killpg, Signal},
+    wait::{waitpid, WaitStatus},
+};
+
+use crate::core::metas::{ContainerStatus, HealthStatus, CONTAINER_METAS};
+
+use super::{exec::exec_probe_process, process::ProcessConfig};
+
+/// Longest a single check may take, a check is cut off at the interval if it is shorter.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Start checking the health of container `id` while its process `pid` is running, if it has a
+/// health check.
+pub fn watch_health(id: String, pid: i32) {
+    tokio::spawn(monitor(id, pid));
+}
+
+async fn monitor(id: String, pid: i32) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let Some(meta) = container_metas.get_meta_by_id(&id).await else {
+        return;
+    };
+    let Some(check) = meta.health_check else {
+        return;
+    };
+    let process = match ProcessConfig::resolve(
+        Path::new(&format!("/proc/{pid}/root")),
+        &meta.env,
+        meta.working_dir.as_deref(),
+        meta.user.as_deref(),
+    ) {
+        Ok(process) => process,
+        Err(e) => {
+            error!("Failed to check container {} health: {:?}", meta.name, e);
+            return;
+        }
+    };
+
+    let mut health = HealthStatus::Starting;
+    if let Err(e) = container_metas
+        .update_health(id.clone(), health.clone())
+        .await
+    {
+        error!("Failed to update container {} health: {:?}", meta.name, e);
+    }
+
+    let interval = Duration::from_secs(check.interval.max(1));
+    let start_period = Duration::from_secs(check.start_period);
+    let started_at = Instant::now();
+    let mut failures = 0;
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        // Stop watching once this run of the container is over.
+        let Some(meta) = container_metas.get_meta_by_id(&id).await else {
+            return;
+        };
+        if meta.state.pid != Some(pid) || !meta.state.status.can_stop() {
+            return;
+        }
+        if meta.state.status == ContainerStatus::Paused {
+            continue;
+        }
+
+        let passed = probe(pid, &check.command, &process, interval.min(PROBE_TIMEOUT)).await;
+        let next = next_health(
+            &health,
+            passed,
+            started_at.elapsed() < start_period,
+            &mut failures,
+            check.retries,
+        );
+
+        if next != health {
+            info!(
+                "[Daemon] Container {} health changed: {:?} -> {:?}",
+                meta.name, health, next
+            );
+            if let Err(e) = container_metas
+                .update_health(id.clone(), next.clone())
+                .await
+            {
+                error!("Failed to update container {} health: {:?}", meta.name, e);
+            }
+            health = next;
+        }
+    }
+}
+
+/// Run the check `command` in the container of `pid`, it passes if exiting with 0 in time.
+async fn probe(pid: i32, command: &[String], process: &ProcessConfig, timeout: Duration) -> bool {
+    let child = match exec_probe_process(pid, command, process) {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to run health check: {:?}", e);
+            return false;
+        }
+    };
+
+    // The blocking wait goes on to reap the child even if timed out.
+    let wait = tokio::task::spawn_blocking(move || waitpid(child, None));
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(Ok(Ok(WaitStatus::Exited(_, 0)))) => true,
+        Ok(_) => false,
+        Err(_) => {
+            // The command is forked off in the container, kill it along with its parent.
+            let _ = killpg(child, Signal::SIGKILL);
+            false
+        }
+    }
+}
+
+/// Health after a check, `failures` counts the failed checks in a row. Failures within the start
+/// period do not count until the container has been healthy once.
+fn next_health(
+    current: &HealthStatus,
+    passed: bool,
+    in_start_period: bool,
+    failures: &mut u32,
+    retries: u32,
+) -> HealthStatus {
+    if passed {
+        *failures = 0;
+        return HealthStatus::Healthy;
+    }
+
+    if in_start_period && *current == HealthStatus::Starting {
+        return HealthStatus::Starting;
+    }
+
+    *failures += 1;
+    if *failures >= retries.max(1) {
+        HealthStatus::Unhealthy
+    } else {
+        current.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_health() {
+        let mut failures = 0;
+
+        // Failures within the start period are ignored.
+        let health = next_health(&HealthStatus::Starting, false, true, &mut failures, 2);
+        assert_eq!(health, HealthStatus::Starting);
+        assert_eq!(failures, 0);
+
+        let health = next_health(&health, true, true, &mut failures, 2);
+        assert_eq!(health, HealthStatus::Healthy);
+
+        // Once healthy, failures count even within the start period.
+        let health = next_health(&health, false, true, &mut failures, 2);
+        assert_eq!(health, HealthStatus::Healthy);
+        assert_eq!(failures, 1);
+
+        let health = next_health(&health, false, false, &mut failures, 2);
+        assert_eq!(health, HealthStatus::Unhealthy);
+
+        // A single pass makes it healthy again.
+        let health = next_health(&health, true, false, &mut failures, 2);
+        assert_eq!(health, HealthStatus::Healthy);
+        assert_eq!(failures, 0);
+    }
+}
@@ -1,47 +1,46 @@
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 
 use log::debug;
-use nix::mount::{mount, umount2, MntFlags, MsFlags};
+use nix::{
+    errno::Errno,
+    mount::{mount, umount2, MntFlags, MsFlags},
+};
 
+use crate::core::metas::{MountPoint, MountType};
+use crate::core::volume::volume_path;
+
+/// Symlinks followed at most resolving a path, as many as the kernel does.
+const MAX_SYMLINKS: usize = 40;
+
+/// Create the workspace of a container, an overlay of its write layer on the image `layers`,
+/// bottom first, with its volumes mounted.
 pub async fn new_workspace(
-    image_path: &str,
+    layers: &[PathBuf],
     root_path: &str,
     mnt_path: &str,
-    volume: &Option<String>,
+    mounts: &[MountPoint],
 ) -> anyhow::Result<()> {
-    let image_path = Path::new(image_path);
     let root_path = Path::new(root_path);
     let mnt_path = Path::new(mnt_path);
 
-    create_ro_layer(&image_path, &root_path).await?;
-    if let Err(e) = create_rw_layer(&root_path).await {
-        // Clean up the ro layer.
-        let _ = tokio::fs::remove_dir_all(root_path).await;
-        return Err(e);
-    }
-    if let Err(e) = create_mount_point(&root_path, &mnt_path).await {
-        // Clean up the ro and rw layers.
+    create_rw_layer(root_path).await?;
+    if let Err(e) = create_mount_point(layers, root_path, mnt_path).await {
+        // Clean up the rw layer.
         let _ = tokio::fs::remove_dir_all(root_path).await;
         return Err(e);
     }
 
-    if let Some(vol) = volume {
-        let sv = vol.split(":").collect::<Vec<&str>>();
-        if sv.len() == 2 && !sv[0].is_empty() && !sv[1].is_empty() {
-            if let Err(e) = mount_volume(&mnt_path, sv).await {
-                // Clean up the ro and rw layers.
-                let _ = Command::new("umount").arg(mnt_path).status();
-                let _ = tokio::fs::remove_dir_all(root_path).await;
-
-                return Err(e);
-            }
-        } else {
-            let _ = Command::new("umount").arg(mnt_path).status();
-            let _ = tokio::fs::remove_dir_all(root_path).await;
+    if let Err(e) = mount_volumes(mnt_path, mounts).await {
+        // Clean up the rw layer.
+        let _ = Command::new("umount").arg(mnt_path).status();
+        let _ = tokio::fs::remove_dir_all(root_path).await;
 
-            return Err(anyhow::anyhow!("Invalid volume: {}", vol));
-        }
+        return Err(e);
     }
 
     debug!("[Daemon] Workspace created under {:?}", root_path);
@@ -49,32 +48,6 @@ pub async fn new_workspace(
     Ok(())
 }
 
-// Create a read-only layer, on the given image.
-async fn create_ro_layer(image_path: &Path, root_path: &Path) -> anyhow::Result<()> {
-    let image_dir = root_path.join("image");
-
-    if !image_dir.exists() {
-        tokio::fs::create_dir_all(&image_dir).await?;
-
-        let output = Command::new("tar")
-            .arg("-xvf")
-            .arg(&image_path)
-            .arg("-C")
-            .arg(&image_dir)
-            .stdout(Stdio::null())
-            .output()?;
-
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Failed to extract image: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-    }
-
-    Ok(())
-}
-
 // Create a read-write layer, which is the container's write layer.
 async fn create_rw_layer(root_path: &Path) -> anyhow::Result<()> {
     let write_dir = root_path.join("writeLayer");
@@ -85,9 +58,13 @@ async fn create_rw_layer(root_path: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn create_mount_point(root_path: &Path, mnt_path: &Path) -> anyhow::Result<()> {
+async fn create_mount_point(
+    layers: &[PathBuf],
+    root_path: &Path,
+    mnt_path: &Path,
+) -> anyhow::Result<()> {
     let upperdir = root_path.join("writeLayer");
-    let lowerdir = root_path.join("image");
+    let lowerdir = lower_dirs(layers);
     let workdir = root_path.join("work");
 
     if !workdir.exists() {
@@ -100,7 +77,7 @@ async fn create_mount_point(root_path: &Path, mnt_path: &Path) -> anyhow::Result
 
     let mount_option = format!(
         "lowerdir={},upperdir={},workdir={}",
-        lowerdir.display(),
+        lowerdir,
         upperdir.display(),
         workdir.display()
     );
@@ -124,58 +101,313 @@ async fn create_mount_point(root_path: &Path, mnt_path: &Path) -> anyhow::Result
     Ok(())
 }
 
+/// Overlay lowerdir of `layers` given bottom first, as overlay takes the top one first.
+pub fn lower_dirs(layers: &[PathBuf]) -> String {
+    layers
+        .iter()
+        .rev()
+        .map(|layer| layer.display().to_string())
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Unmount the recorded mounts and the overlay of a workspace, then delete it. What is already
+/// gone is skipped, so that a failed removal can be tried again.
 pub async fn delete_workspace(
     root_path: &str,
     mnt_path: &str,
-    volume: &Option<String>,
+    mounts: &[MountPoint],
 ) -> anyhow::Result<()> {
     let root_path = Path::new(root_path);
     let mnt_path = Path::new(mnt_path);
 
-    if let Some(vol) = volume {
-        let sv = vol.split(":").collect::<Vec<&str>>();
+    umount_volumes(mnt_path, mounts)?;
 
-        assert!(sv.len() == 2 && !sv[0].is_empty() && !sv[1].is_empty());
-        umount_volume(mnt_path, sv).await?;
+    // Unmount the overlay filesystem.
+    match umount2(mnt_path, MntFlags::MNT_DETACH) {
+        Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => {}
+        Err(e) => return Err(anyhow::anyhow!("Failed to unmount rootfs: {}", e)),
     }
+    // Anything still mounted within would be deleted through.
+    umount_all(root_path)?;
 
-    // Unmount the overlay filesystem.
-    Command::new("umount").arg(mnt_path).status()?;
     // And simply delete the whole directory.
-    tokio::fs::remove_dir_all(root_path).await?;
+    match tokio::fs::remove_dir_all(root_path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
 
     debug!("[Daemon] Workspace deleted under {:?}", root_path);
 
     Ok(())
 }
 
-async fn mount_volume(mnt_path: &Path, volume_path: Vec<&str>) -> anyhow::Result<()> {
-    let hostv = Path::new(volume_path[0]);
-    let contv = mnt_path.join(volume_path[1].strip_prefix("/").unwrap());
+/// Unmount everything at or under `path`, nested mounts first, and make sure nothing is left, so
+/// that deleting it cannot reach into a mounted filesystem.
+pub fn umount_all(path: &Path) -> anyhow::Result<()> {
+    for mount_point in mounts_under(path)?.iter().rev() {
+        match umount2(mount_point, MntFlags::MNT_DETACH) {
+            Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to unmount {:?}: {}",
+                    mount_point,
+                    e
+                ))
+            }
+        }
+    }
+
+    match mounts_under(path)?.first() {
+        Some(mount_point) => Err(anyhow::anyhow!("{:?} is still mounted", mount_point)),
+        None => Ok(()),
+    }
+}
+
+/// Mount points at or under `path`, in the order they were mounted.
+fn mounts_under(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+
+    Ok(mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(unescape_mount_point)
+        .filter(|mount_point| mount_point.starts_with(path))
+        .collect())
+}
+
+/// Mount point as listed in mountinfo, where space, tab, newline and backslash are octal escapes.
+fn unescape_mount_point(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|digits| {
+            bytes[i] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match escape {
+            Some(digits) => {
+                unescaped.push(
+                    digits
+                        .iter()
+                        .fold(0u32, |n, digit| n * 8 + u32::from(digit - b'0'))
+                        as u8,
+                );
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    PathBuf::from(std::ffi::OsString::from_vec(unescaped))
+}
+
+/// Mount the volumes of a container into its rootfs, in order, as one may be nested in another.
+pub async fn mount_volumes(mnt_path: &Path, mounts: &[MountPoint]) -> anyhow::Result<()> {
+    for (i, mount_point) in mounts.iter().enumerate() {
+        if let Err(e) = mount_volume(mnt_path, mount_point).await {
+            // Clean up the mounts done.
+            let _ = umount_volumes(mnt_path, &mounts[..i]);
 
+            return Err(anyhow::anyhow!(
+                "Failed to mount {}: {}",
+                mount_point.destination,
+                e
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmount the volumes of a container, skipping those not mounted.
+pub fn umount_volumes(mnt_path: &Path, mounts: &[MountPoint]) -> anyhow::Result<()> {
+    // Nested mounts go before those they are mounted on.
+    for mount_point in mounts.iter().rev() {
+        umount_volume(mnt_path, mount_point)
+            .map_err(|e| anyhow::anyhow!("Failed to unmount {}: {}", mount_point.destination, e))?;
+    }
+
+    Ok(())
+}
+
+/// Host directory of a bind mount or a volume.
+fn host_path(mount_point: &MountPoint) -> PathBuf {
+    match mount_point.mount_type {
+        MountType::Volume => volume_path(&mount_point.source),
+        _ => PathBuf::from(&mount_point.source),
+    }
+}
+
+async fn mount_volume(mnt_path: &Path, mount_point: &MountPoint) -> anyhow::Result<()> {
+    let contv = resolve_in_root(mnt_path, &mount_point.destination)?;
+
+    let mut flags = MsFlags::empty();
+    if mount_point.read_only {
+        flags |= MsFlags::MS_RDONLY;
+    }
+
+    if mount_point.mount_type == MountType::Tmpfs {
+        if !contv.exists() {
+            tokio::fs::create_dir_all(&contv).await?;
+        }
+
+        let data = mount_point.size.map(|size| format!("size={size}"));
+        mount(Some("tmpfs"), &contv, Some("tmpfs"), flags, data.as_deref())?;
+
+        return Ok(());
+    }
+
+    let hostv = host_path(mount_point);
     if !hostv.exists() {
-        tokio::fs::create_dir_all(hostv).await?;
+        tokio::fs::create_dir_all(&hostv).await?;
     }
 
+    // A file is bound onto a file.
     if !contv.exists() {
-        tokio::fs::create_dir_all(&contv).await?;
+        if hostv.is_file() {
+            if let Some(parent) = contv.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::File::create(&contv).await?;
+        } else {
+            tokio::fs::create_dir_all(&contv).await?;
+        }
     }
 
     mount(
-        Some(hostv),
+        Some(&hostv),
         &contv,
         None::<&str>,
         MsFlags::MS_BIND,
         None::<&str>,
     )?;
 
+    // A bind mount only becomes read-only once remounted.
+    if mount_point.read_only {
+        if let Err(e) = mount(
+            None::<&str>,
+            &contv,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | flags,
+            None::<&str>,
+        ) {
+            let _ = umount2(&contv, MntFlags::MNT_DETACH);
+            return Err(e.into());
+        }
+    }
+
     Ok(())
 }
 
-async fn umount_volume(mnt_path: &Path, volume_path: Vec<&str>) -> anyhow::Result<()> {
-    let contv = mnt_path.join(volume_path[1].strip_prefix("/").unwrap());
+/// Unmount a volume of a workspace, unless it is not mounted.
+fn umount_volume(mnt_path: &Path, mount_point: &MountPoint) -> anyhow::Result<()> {
+    let contv = resolve_in_root(mnt_path, &mount_point.destination)?;
 
-    umount2(&contv, MntFlags::MNT_DETACH)?;
+    match umount2(&contv, MntFlags::MNT_DETACH) {
+        Ok(()) | Err(Errno::EINVAL) | Err(Errno::ENOENT) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    Ok(())
+/// Resolve `path` within `root` as if it were `/`: symlinks of the image are followed, but
+/// neither they nor `..` lead out of it. What does not exist yet is kept as is, to be created.
+pub(super) fn resolve_in_root(root: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    // Components left to resolve, `..` included.
+    let mut pending: VecDeque<OsString> = VecDeque::new();
+    push_components(&mut pending, Path::new(path));
+
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(component) = pending.pop_front() {
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        let next = resolved.join(&component);
+        let is_symlink = root
+            .join(&next)
+            .symlink_metadata()
+            .is_ok_and(|meta| meta.file_type().is_symlink());
+        if !is_symlink {
+            resolved = next;
+            continue;
+        }
+
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return Err(anyhow::anyhow!("Too many symlinks resolving {}", path));
+        }
+        let target = std::fs::read_link(root.join(&next))?;
+        if target.is_absolute() {
+            resolved = PathBuf::new();
+        }
+        let mut rest = VecDeque::new();
+        push_components(&mut rest, &target);
+        rest.append(&mut pending);
+        pending = rest;
+    }
+
+    Ok(root.join(resolved))
+}
+
+fn push_components(components: &mut VecDeque<OsString>, path: &Path) {
+    for component in path.components() {
+        match component {
+            Component::ParentDir => components.push_back("..".into()),
+            Component::Normal(name) => components.push_back(name.to_os_string()),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape_mount_point() {
+        assert_eq!(
+            unescape_mount_point("/tmp/rtain/web-1/mnt"),
+            PathBuf::from("/tmp/rtain/web-1/mnt")
+        );
+        assert_eq!(
+            unescape_mount_point(r"/mnt/my\040data\134x"),
+            PathBuf::from(r"/mnt/my data\x")
+        );
+        assert_eq!(unescape_mount_point(r"/a\9"), PathBuf::from(r"/a\9"));
+    }
+
+    #[test]
+    fn test_resolve_in_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::os::unix::fs::symlink("/etc", root.join("abs")).unwrap();
+        std::os::unix::fs::symlink("../../../etc", root.join("etc/up")).unwrap();
+        std::os::unix::fs::symlink("loop", root.join("loop")).unwrap();
+
+        assert_eq!(
+            resolve_in_root(root, "/data/db").unwrap(),
+            root.join("data/db")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/abs/conf").unwrap(),
+            root.join("etc/conf")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/etc/up/conf").unwrap(),
+            root.join("etc/conf")
+        );
+        assert_eq!(
+            resolve_in_root(root, "/../../etc").unwrap(),
+            root.join("etc")
+        );
+        assert!(resolve_in_root(root, "/loop").is_err());
+    }
 }
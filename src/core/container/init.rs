@@ -1,5 +1,5 @@
 use std::{
-    ffi::CString,
+    collections::HashMap,
     io::{Read, Write},
     os::{fd::AsRawFd, unix::net::UnixStream as StdUnixStream},
     path::Path,
@@ -14,8 +14,11 @@ use nix::{
     mount::{mount, umount2, MntFlags, MsFlags},
     pty::{openpty, OpenptyResult},
     sched::{clone, CloneFlags},
-    sys::wait::{waitpid, WaitPidFlag, WaitStatus},
-    unistd::{chdir, dup2, execvp, pivot_root, read, write, Pid},
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{chdir, dup2, pivot_root, read, write, Pid},
 };
 use rand::{thread_rng, Rng};
 use tokio::{
@@ -28,11 +31,21 @@ use tokio::{
 use crate::core::{
     cmd::RunArgs,
     container::stop::do_stop,
-    metas::{ContainerMeta, CONTAINER_METAS},
+    image::{acquire_image, find_image, layer_root, release_layers, RunConfig},
+    metas::{ContainerMeta, HealthCheckConfig, MountPoint, ResourceConfig, CONTAINER_METAS},
+    network::{connect_container, publish_ports, release_container, unpublish_ports},
+    volume::{acquire_volumes, release_volumes},
     Msg, ROOT_PATH,
 };
 
-use super::image::{delete_workspace, new_workspace};
+use super::{
+    health::watch_health,
+    image::{delete_workspace, new_workspace},
+    label::merge_labels,
+    process::{merge_env, ProcessConfig},
+    resource::{apply_resources, merge_resources},
+    restart::{exit_code, request_stop, supervise},
+};
 
 /// Run a new container from given image.
 pub async fn run_container(run_args: RunArgs, mut stream: UnixStream) {
@@ -57,23 +70,26 @@ pub async fn run_container(run_args: RunArgs, mut stream: UnixStream) {
             return;
         }
     };
-    do_run(meta.name, meta.id, pid, pty, sock, stream, detach, true).await;
+    watch_health(meta.id.clone(), pid.as_raw());
+
+    let stream = (!detach).then_some(stream);
+    if let Some(status) = do_run(meta.name, meta.id.clone(), pid, pty, sock, stream, true).await {
+        supervise(meta.id, exit_code(status)).await;
+    }
 }
 
+/// Drive the container process `child` until it exits, attached to the client `stream` if given,
+/// otherwise with its outputs going to the log file. Returns how the container exited, or `None`
+/// if it was not seen exiting, e.g. the client went away first.
 pub async fn do_run(
     name: String,
     id: String,
     child: Pid,
     pty: OpenptyResult,
     mut p_sock: StdUnixStream,
-    stream: UnixStream,
-    detach: bool,
+    stream: Option<UnixStream>,
     stop_after_exit: bool,
-) {
-    let (stream_reader, stream_writer) = stream.into_split();
-    let stream_reader = Arc::new(Mutex::new(stream_reader));
-    let stream_writer = Arc::new(Mutex::new(stream_writer));
-
+) -> Option<WaitStatus> {
     let name_id = format!("{name}-{id}");
     let root_path = format!("{}/{}", ROOT_PATH, name_id);
 
@@ -95,7 +111,7 @@ pub async fn do_run(
             error!("Failed to open log file: {:?}", e);
             p_sock.write(b"EXIT").unwrap();
 
-            return;
+            return None;
         }
     };
 
@@ -128,9 +144,13 @@ pub async fn do_run(
 
     p_sock.write(b"CONT").unwrap();
 
-    if !detach {
+    let exit = if let Some(stream) = stream {
         debug!("[Daemon]: Attach, redirecting stdio to PTY");
 
+        let (stream_reader, stream_writer) = stream.into_split();
+        let stream_reader = Arc::new(Mutex::new(stream_reader));
+        let stream_writer = Arc::new(Mutex::new(stream_writer));
+
         Msg::Continue
             .send_to(&mut *stream_writer.lock().await)
             .await
@@ -189,7 +209,7 @@ pub async fn do_run(
         });
 
         // Child exits watcher
-        let check_child_exit = tokio::spawn(async move {
+        let mut check_child_exit = tokio::spawn(async move {
             async fn signal_driven_wait(pid: Pid) -> anyhow::Result<WaitStatus> {
                 let mut sigchild = signal(SignalKind::child())?;
 
@@ -205,20 +225,29 @@ pub async fn do_run(
 
             signal_driven_wait(child).await
         });
-        tokio::select! {
+        let exit = tokio::select! {
             _ = client_to_pty => {
                 // Write to PTY finished, client exits, and in current impl, we end the container here.
                 debug!("[Daemon]: Client exits, stopping container");
 
                 pty_to_client.abort();
+                if stop_after_exit {
+                    // Ended on purpose, so not to be restarted, but still reaped for its status.
+                    request_stop(&id).await;
+                    let _ = kill(child, Signal::SIGKILL);
+                    check_child_exit.await.ok().and_then(Result::ok)
+                } else {
+                    check_child_exit.abort();
+                    None
+                }
             }
-            wait_res = check_child_exit => {
+            wait_res = &mut check_child_exit => {
                 // Child process exited.
                 debug!("[Daemon]: Container exited");
 
                 // The container exit, inform the client.
                 pty_to_client.abort();
-                match wait_res.unwrap() {
+                let exit = match wait_res.unwrap() {
                     Ok(status) => {
                         match status {
                             WaitStatus::Exited(_, code) => {
@@ -241,6 +270,7 @@ pub async fn do_run(
                                 error!("[Daemon] {}", msg);
                             }
                         }
+                        Some(status)
                     }
                     Err(e) => {
                         let msg = format!("Error waiting for container: {:?}", e);
@@ -248,13 +278,17 @@ pub async fn do_run(
                             error!("[Daemon] Failed to write to stream: {}", e);
                         }
                         error!("[Daemon] {}", msg);
+                        None
                     }
                 };
                 if let Err(e) = stream_writer.lock().await.shutdown().await {
                     error!("[Daemon] Failed to shutdown stream: {}", e);
                 }
+                exit
             }
-        }
+        };
+
+        exit
     } else {
         debug!("[Daemon]: Detach, redirecting stdio to log file");
         let pty_to_log = tokio::spawn(async move {
@@ -271,16 +305,30 @@ pub async fn do_run(
             }
         });
 
-        // Child exits watcher
-        let _ = tokio::join!(tokio::spawn(async move { waitpid(child, None) }));
+        // Child exits watcher, blocking so keep it off the runtime workers.
+        let wait_res = tokio::task::spawn_blocking(move || waitpid(child, None)).await;
 
         read_from_pty.abort();
         pty_to_log.abort();
-    }
+
+        match wait_res {
+            Ok(Ok(status)) => Some(status),
+            Ok(Err(e)) => {
+                error!("[Daemon] Error waiting for container: {:?}", e);
+                None
+            }
+            Err(e) => {
+                error!("[Daemon] Error waiting for container: {:?}", e);
+                None
+            }
+        }
+    };
 
     if stop_after_exit {
-        do_stop(name, id).await;
+        do_stop(name, id, exit).await;
     }
+
+    exit
 }
 
 async fn run_prepare(
@@ -288,7 +336,7 @@ async fn run_prepare(
 ) -> anyhow::Result<(OpenptyResult, StdUnixStream, ContainerMeta)> {
     // Generate name-id.
     let id = random_id();
-    let name = run_args.name.unwrap_or_else(|| id.clone());
+    let name = run_args.name.clone().unwrap_or_else(|| id.clone());
     let name_id = format!("{}-{}", name, id);
 
     // Root is where we store needed info and the image for the container.
@@ -296,6 +344,48 @@ async fn run_prepare(
     // And the mnt is where we mount the image as container's sysroot.
     let mnt_path = format!("{}/{}/mnt", ROOT_PATH, name_id);
 
+    // Check the resource limits before anything is created.
+    let resources = merge_resources(&ResourceConfig::default(), &run_args.resources)?;
+    let labels = merge_labels(&run_args.labels, &run_args.label_file)?;
+    let mut mounts = container_mounts(&run_args)?;
+
+    // The image config gives the defaults of what to run and how.
+    let image = find_image(&run_args.image).await?;
+    let config = RunConfig::parse(image.config.as_deref())?;
+    let (command, args) = config.command(run_args.entrypoint.as_deref(), &run_args.command)?;
+    let env = merge_env(&config.env(), &run_args.process)?;
+    let working_dir = run_args
+        .process
+        .workdir
+        .clone()
+        .or(config.working_dir().map(str::to_string));
+    let user = run_args
+        .process
+        .user
+        .clone()
+        .or(config.user().map(str::to_string));
+
+    // And the published ports, which are only reachable through a network.
+    let mut ports = HashMap::new();
+    for &(host_port, container_port) in &run_args.publish {
+        if ports.insert(host_port, container_port).is_some() {
+            return Err(anyhow::anyhow!(
+                "Host port {} is published twice",
+                host_port
+            ));
+        }
+    }
+    // Exposed ports are published on free host ports, picked when publishing. Ports published
+    // explicitly are left as they are.
+    let mut exposed = vec![];
+    if run_args.publish_all {
+        exposed = config.exposed_tcp_ports();
+        exposed.retain(|port| !ports.values().any(|published| published == port));
+    }
+    if (!ports.is_empty() || !exposed.is_empty()) && run_args.net.is_none() {
+        return Err(anyhow::anyhow!("Publishing ports requires --net"));
+    }
+
     // If not detach, we need to stream the container io to clients.
     let pty = openpty(None, None)?;
 
@@ -304,28 +394,57 @@ async fn run_prepare(
     let mut buf = [0u8; 4];
 
     // Here we create the whole workspace.
-    new_workspace(&run_args.image, &root_path, &mnt_path, &run_args.volume).await?;
+    let image = acquire_image(&image.id).await?;
+    if let Err(e) = acquire_volumes(&mut mounts).await {
+        release_layers(&image.layers).await;
+        return Err(e);
+    }
+    let layers: Vec<_> = image
+        .layers
+        .iter()
+        .map(|digest| layer_root(digest))
+        .collect();
+    if let Err(e) = new_workspace(&layers, &root_path, &mnt_path, &mounts).await {
+        release_volumes(&mounts, true).await;
+        release_layers(&image.layers).await;
+        return Err(e);
+    }
+
+    // Users are looked up in the image.
+    let process = match ProcessConfig::resolve(
+        Path::new(&mnt_path),
+        &env,
+        working_dir.as_deref(),
+        user.as_deref(),
+    ) {
+        Ok(process) => process,
+        Err(e) => {
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
+            return Err(e);
+        }
+    };
 
     // Create a new process with new namespaces.
-    let child = match new_container_process(&mnt_path, c_sock, &pty, &run_args.command) {
+    let command_line: Vec<_> = command.iter().chain(&args).cloned().collect();
+    let child = match new_container_process(&mnt_path, c_sock, &pty, &command_line, &process) {
         Ok(child) => child,
         Err(e) => {
             // Clone child failure, clean up.
-            let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
             return Err(e);
         }
     };
 
     // Wait for child ready.
     if let Err(e) = p_sock.read_exact(&mut buf) {
-        let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+        let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
         return Err(anyhow::anyhow!("Failed to read from child process: {}", e));
     }
 
     match &buf {
         b"EXIT" => {
             // Child failed to initialize, clean up.
-            delete_workspace(&root_path, &mnt_path, &run_args.volume).await?;
+            discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await?;
 
             return Err(anyhow::anyhow!(
                 "Failed to initialize container: child unexpected exit"
@@ -333,7 +452,7 @@ async fn run_prepare(
         }
         b"WAIT" => {}
         _ => {
-            let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
             return Err(anyhow::anyhow!(
                 "Unexpected message from child process: {:?}",
                 std::str::from_utf8(&buf).unwrap_or("invalid utf8")
@@ -342,55 +461,136 @@ async fn run_prepare(
     }
 
     // Setting up cgroups
-    let cg = match setup_cgroup(&name_id, child) {
+    let cg = match setup_cgroup(&name_id, child, &resources) {
         Ok(cg) => cg,
         Err(e) => {
             let _ = p_sock.write(b"EXIT");
-            let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
 
             return Err(anyhow::anyhow!("Failed to setup cgroup: {:?}", e));
         }
     };
 
+    // Connect the container to the network, within its new net namespace.
+    let network = match &run_args.net {
+        Some(net) => match connect_container(net, &id, child.as_raw(), None).await {
+            Ok(mut network) => {
+                network.ports = ports;
+                if let Err(e) = publish_ports(&id, &mut network, &exposed).await {
+                    let _ = p_sock.write(b"EXIT");
+                    let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
+                    let _ = cg.delete();
+                    let _ = release_container(&network).await;
+
+                    return Err(anyhow::anyhow!("Failed to publish ports: {:?}", e));
+                }
+
+                Some(network)
+            }
+            Err(e) => {
+                let _ = p_sock.write(b"EXIT");
+                let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
+                let _ = cg.delete();
+
+                return Err(anyhow::anyhow!(
+                    "Failed to connect network {}: {:?}",
+                    net,
+                    e
+                ));
+            }
+        },
+        None => None,
+    };
+
     // Form the container record.
-    let cm = ContainerMeta::new(
+    let mut cm = ContainerMeta::new(
         id.clone(),
         name.clone(),
         run_args.image.clone(),
-        run_args.command.clone(),
-        vec![], // No args field in RunArgs, use empty vector
+        command,
+        args,
     );
+    cm.image_id = image.id.clone();
+    cm.layers = image.layers.clone();
+    cm.env = env;
+    cm.labels = labels;
+    cm.working_dir = working_dir;
+    cm.user = user;
+    cm.resources = resources;
+    cm.restart_policy = run_args.restart.clone();
+    cm.health_check = run_args
+        .health
+        .health_cmd
+        .as_ref()
+        .map(|cmd| HealthCheckConfig {
+            command: vec!["/bin/sh".to_string(), "-c".to_string(), cmd.clone()],
+            interval: run_args.health.health_interval,
+            retries: run_args.health.health_retries,
+            start_period: run_args.health.health_start_period,
+        });
+    cm.set_running(child.as_raw());
 
     let container_metas = match CONTAINER_METAS.get() {
         Some(metas) => metas,
         None => {
             let _ = p_sock.write(b"EXIT");
-            let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
             return Err(anyhow::anyhow!("Container metas not initialized"));
         }
     };
 
     if let Err(e) = container_metas.register(cm.clone()).await {
         let _ = p_sock.write(b"EXIT");
-        let _ = delete_workspace(&root_path, &mnt_path, &run_args.volume).await;
+        let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
         let _ = cg.delete();
+        if let Some(network) = &network {
+            let _ = unpublish_ports(&id, network).await;
+            let _ = release_container(network).await;
+        }
 
         return Err(anyhow::anyhow!("Failed to register container: {:?}", e));
     }
 
+    if let Some(network) = network {
+        if let Err(e) = container_metas
+            .attach_network(id.clone(), network.clone())
+            .await
+        {
+            let _ = p_sock.write(b"EXIT");
+            let _ = container_metas.deregister(id.clone()).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
+            let _ = cg.delete();
+            let _ = unpublish_ports(&id, &network).await;
+            let _ = release_container(&network).await;
+
+            return Err(anyhow::anyhow!("Failed to attach network: {:?}", e));
+        }
+        cm.network = Some(network);
+    }
+
+    for mount in &mounts {
+        if let Err(e) = container_metas.add_mount(id.clone(), mount.clone()).await {
+            let _ = p_sock.write(b"EXIT");
+            let _ = container_metas.deregister(id.clone()).await;
+            let _ = discard_workspace(&root_path, &mnt_path, &mounts, &image.layers).await;
+            let _ = cg.delete();
+            if let Some(network) = &cm.network {
+                let _ = unpublish_ports(&id, network).await;
+                let _ = release_container(network).await;
+            }
+
+            return Err(anyhow::anyhow!("Failed to add mount: {:?}", e));
+        }
+    }
+    cm.mounts = mounts;
+
     Ok((pty, p_sock, cm))
 }
 
 /// This is the first process in the new namespace.
-fn do_init(command: &Vec<String>) -> anyhow::Result<()> {
-    let command_cstr = CString::new(command[0].clone())?;
-    let args_cstr: Vec<CString> = command
-        .iter()
-        .map(|arg| CString::new(arg.clone()).unwrap())
-        .collect();
-
+fn do_init(command: &Vec<String>, process: &ProcessConfig) -> anyhow::Result<()> {
     info!("Ready to run command: {:?}", command);
-    execvp(&command_cstr, &args_cstr)?;
+    process.exec(command)?;
 
     Ok(())
 }
@@ -401,6 +601,7 @@ pub fn new_container_process(
     mut c_sock: StdUnixStream,
     pty: &OpenptyResult,
     command: &Vec<String>,
+    process: &ProcessConfig,
 ) -> anyhow::Result<Pid> {
     // NOTICE: In current impl, we always create new namespaces for the container, rather than
     // keep alive the old ones.
@@ -462,7 +663,7 @@ pub fn new_container_process(
             _ => unreachable!(),
         }
 
-        if let Err(e) = do_init(command) {
+        if let Err(e) = do_init(command, process) {
             error!("Failed to initialize container: {:?}", e);
             return -1;
         }
@@ -482,13 +683,19 @@ pub fn new_container_process(
     Ok(child_pid)
 }
 
-fn setup_cgroup(cg_name: &str, child: Pid) -> anyhow::Result<Cgroup> {
+fn setup_cgroup(cg_name: &str, child: Pid, resources: &ResourceConfig) -> anyhow::Result<Cgroup> {
     let hier = cgroups_rs::hierarchies::auto();
-    let cg = match CgroupBuilder::new(&cg_name).build(hier) {
+    let cg = match CgroupBuilder::new(cg_name).build(hier) {
         Ok(cg) => cg,
         Err(e) => return Err(anyhow::anyhow!("Failed to create cgroup: {:?}", e)),
     };
 
+    // Limits go first, so the container never runs unconstrained.
+    if let Err(e) = apply_resources(&cg, resources) {
+        cg.delete()?;
+        return Err(e);
+    }
+
     if let Err(e) = cg.add_task_by_tgid(CgroupPid::from(child.as_raw() as u64)) {
         cg.delete()?;
         return Err(anyhow::anyhow!("Failed to add task to cgroup: {:?}", e));
@@ -556,7 +763,42 @@ fn switch_root(root: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn random_id() -> String {
+/// Mounts of `-v` then `--mount`.
+fn container_mounts(run_args: &RunArgs) -> anyhow::Result<Vec<MountPoint>> {
+    let mut mounts: Vec<MountPoint> = vec![];
+
+    for mount in run_args.volumes.iter().chain(&run_args.mounts) {
+        if mounts
+            .iter()
+            .any(|other| other.destination == mount.destination)
+        {
+            return Err(anyhow::anyhow!(
+                "Duplicate mount point {}",
+                mount.destination
+            ));
+        }
+
+        mounts.push(mount.clone());
+    }
+
+    Ok(mounts)
+}
+
+/// Undo the workspace of a container that failed to run, and give its volumes and layers back.
+async fn discard_workspace(
+    root_path: &str,
+    mnt_path: &str,
+    mounts: &[MountPoint],
+    layers: &[String],
+) -> anyhow::Result<()> {
+    let res = delete_workspace(root_path, mnt_path, mounts).await;
+    release_volumes(mounts, true).await;
+    release_layers(layers).await;
+
+    res
+}
+
+pub fn random_id() -> String {
     let mut rng = thread_rng();
     let random_bytes: [u8; 16] = rng.gen();
 
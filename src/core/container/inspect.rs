@@ -0,0 +1,171 @@
+use log::error;
+use serde_json::{json, Value};
+use tokio::net::UnixStream;
+
+use super::image::lower_dirs;
+
+use crate::core::{
+    cmd::InspectArgs,
+    image::layer_root,
+    metas::{ContainerMeta, CONTAINER_METAS},
+    Msg, ROOT_PATH,
+};
+
+/// Show the full record of a container as JSON, or formatted by a template.
+pub async fn inspect_container(inspect_args: InspectArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&inspect_args.name).await {
+        Some(meta) => Some(meta),
+        None => container_metas.get_meta_by_id(&inspect_args.name).await,
+    };
+    let Some(meta) = meta else {
+        error!(
+            "Failed to inspect container {}, record does not exist",
+            &inspect_args.name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to inspect container {}, record does not exist",
+            &inspect_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    };
+
+    let output = to_record(&meta).and_then(|record| match &inspect_args.format {
+        Some(format) => render(format, &record),
+        None => Ok(serde_json::to_string_pretty(&record)?),
+    });
+
+    match output {
+        Ok(output) => {
+            let _ = Msg::OkContent(output).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!(
+                "Failed to inspect container {}: {:?}",
+                &inspect_args.name, e
+            );
+            let _ = Msg::Err(format!(
+                "Failed to inspect container {}: {}",
+                &inspect_args.name, e
+            ))
+            .send_to(&mut stream)
+            .await;
+        }
+    }
+}
+
+/// The container record, with the paths of its workspace added.
+fn to_record(meta: &ContainerMeta) -> anyhow::Result<Value> {
+    let root_path = format!("{}/{}-{}", ROOT_PATH, meta.name, meta.id);
+
+    let layers: Vec<_> = meta
+        .layers
+        .iter()
+        .map(|digest| layer_root(digest))
+        .collect();
+
+    let mut record = serde_json::to_value(meta)?;
+    record["paths"] = json!({
+        "root": root_path,
+        "lower_dir": lower_dirs(&layers),
+        "upper_dir": format!("{root_path}/writeLayer"),
+        "work_dir": format!("{root_path}/work"),
+        "merged_dir": format!("{root_path}/mnt"),
+        "log_path": format!("{root_path}/log.log"),
+    });
+
+    Ok(record)
+}
+
+/// Render a Go-template-style `format`, where `{{.State.ExitCode}}` is replaced by the field
+/// `state.exit_code` of `record`, and `{{json .Network}}` by the field as JSON.
+fn render(format: &str, record: &Value) -> anyhow::Result<String> {
+    let mut output = String::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find("}}")
+            .ok_or(anyhow::anyhow!("Unclosed action in format"))?
+            + start;
+        let action = rest[start + 2..end].trim();
+
+        match action.strip_prefix("json ") {
+            Some(path) => output.push_str(&lookup(record, path.trim())?.to_string()),
+            None => match lookup(record, action)? {
+                Value::String(s) => output.push_str(s),
+                Value::Null => output.push_str("<nil>"),
+                value => output.push_str(&value.to_string()),
+            },
+        }
+
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// Field of `record` at `path` like `.State.Pid`, names match ignoring case and underscores.
+fn lookup<'a>(record: &'a Value, path: &str) -> anyhow::Result<&'a Value> {
+    let fields = path
+        .strip_prefix('.')
+        .ok_or(anyhow::anyhow!("Invalid field {path}, must start with ."))?;
+
+    let normalize = |key: &str| -> String {
+        key.chars()
+            .filter(|c| *c != '_')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+
+    let mut value = record;
+    for field in fields.split('.').filter(|field| !field.is_empty()) {
+        let field = normalize(field);
+
+        value = value
+            .as_object()
+            .and_then(|map| map.iter().find(|(key, _)| normalize(key) == field))
+            .map(|(_, value)| value)
+            .ok_or(anyhow::anyhow!("Field {path} not found"))?;
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let record = json!({
+            "name": "web",
+            "state": { "status": "Running", "exit_code": null, "pid": 42 },
+            "labels": { "app": "nginx" },
+        });
+
+        assert_eq!(render("{{.Name}}", &record).unwrap(), "web");
+        assert_eq!(
+            render("{{.State.Status}} {{ .state.pid }}", &record).unwrap(),
+            "Running 42"
+        );
+        assert_eq!(render("{{.State.ExitCode}}", &record).unwrap(), "<nil>");
+        assert_eq!(
+            render("labels={{json .Labels}}", &record).unwrap(),
+            r#"labels={"app":"nginx"}"#
+        );
+        assert_eq!(render("{{json .}}", &record).unwrap(), record.to_string());
+        assert_eq!(render("plain", &record).unwrap(), "plain");
+
+        assert!(render("{{.Missing}}", &record).is_err());
+        assert!(render("{{Name}}", &record).is_err());
+        assert!(render("{{.Name", &record).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use cgroups_rs::Cgroup;
+use log::info;
+use nix::{
+    errno::Errno,
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::net::UnixStream;
+
+use crate::core::{cmd::KillArgs, metas::CONTAINER_METAS};
+
+use super::bulk::{reply_selection_error, run_bulk, select_containers};
+
+/// Send a signal to running containers, the record is left to the waiter of each container in
+/// case it exits.
+pub async fn kill_container(kill_args: KillArgs, mut stream: UnixStream) {
+    let names = match select_containers(&kill_args.names, &kill_args.filters).await {
+        Ok(names) => names,
+        Err(e) => {
+            reply_selection_error("kill", e, &mut stream).await;
+            return;
+        }
+    };
+
+    let signal = match Signal::try_from(kill_args.signal) {
+        Ok(signal) => signal,
+        Err(e) => {
+            reply_selection_error("kill", e.into(), &mut stream).await;
+            return;
+        }
+    };
+
+    run_bulk(
+        names,
+        |name| kill_one(name, signal, kill_args.all),
+        &mut stream,
+    )
+    .await;
+}
+
+async fn kill_one(name: String, signal: Signal, all: bool) -> anyhow::Result<String> {
+    let meta = CONTAINER_METAS
+        .get()
+        .unwrap()
+        .get_meta_by_name(&name)
+        .await
+        .ok_or(anyhow::anyhow!(
+            "Failed to kill container {name}, record does not exist"
+        ))?;
+
+    let pid = match meta.state.pid {
+        Some(pid) if meta.state.status.can_stop() => pid,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Failed to kill container {name}, it's not running"
+            ))
+        }
+    };
+
+    let pids = if all {
+        let hier = cgroups_rs::hierarchies::auto();
+        let cg = Cgroup::load(hier, format!("{}-{}", meta.name, meta.id));
+
+        cg.procs().into_iter().map(|pid| pid.pid as i32).collect()
+    } else {
+        vec![pid]
+    };
+
+    for pid in pids {
+        match kill(Pid::from_raw(pid), signal) {
+            // Already gone, which the waiter takes care of.
+            Ok(()) | Err(Errno::ESRCH) => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to kill container {name}, cannot send {signal} to process {pid}: {e}"
+                ))
+            }
+        }
+    }
+
+    info!("[Daemon] Sent {} to container {}", signal, &name);
+    Ok(format!("Sent {signal} to container {name}"))
+}
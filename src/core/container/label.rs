@@ -0,0 +1,164 @@
+use std::{collections::HashMap, fs::read_to_string};
+
+use log::{error, info};
+use tokio::{net::UnixStream, sync::Mutex};
+
+use crate::core::{
+    cmd::{LabelAddArgs, LabelRmArgs},
+    metas::CONTAINER_METAS,
+    Msg,
+};
+
+/// Held while the labels of a container are read, changed and written back, so that concurrent
+/// changes do not drop each other.
+static LABEL_UPDATES: Mutex<()> = Mutex::const_new(());
+
+/// Labels of a new container, from the label files then the labels given.
+pub fn merge_labels(
+    labels: &[(String, String)],
+    label_files: &[String],
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut merged = HashMap::new();
+
+    for path in label_files {
+        let content = read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read label file {path}: {e}"))?;
+        merged.extend(parse_label_lines(&content)?);
+    }
+    merged.extend(labels.iter().cloned());
+
+    Ok(merged)
+}
+
+/// Parse a label file, a `key=value` per line as given to `--label`, so a lone key has an empty
+/// value. Blank lines and comments starting with `#` are skipped.
+fn parse_label_lines(content: &str) -> anyhow::Result<Vec<(String, String)>> {
+    content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(
+            |(n, line)| match line.split_once('=').unwrap_or((line, "")) {
+                ("", _) => Err(anyhow::anyhow!(
+                    "Invalid label file line {}: {}",
+                    n + 1,
+                    line
+                )),
+                (key, value) => Ok((key.to_string(), value.to_string())),
+            },
+        )
+        .collect()
+}
+
+/// Add labels to a container, replacing those with the same keys.
+pub async fn add_labels(add_args: LabelAddArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+    let _updating = LABEL_UPDATES.lock().await;
+
+    let Some(meta) = container_metas.get_meta_by_name(&add_args.name).await else {
+        error!(
+            "Failed to label container {}, record does not exist",
+            &add_args.name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to label container {}, record does not exist",
+            &add_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    };
+
+    let mut labels = meta.labels;
+    labels.extend(add_args.labels);
+
+    if let Err(e) = container_metas.update_labels(meta.id, labels).await {
+        error!("Failed to update container labels: {:?}", e);
+        let _ = Msg::Err(format!("Failed to update container labels: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    info!("[Daemon] Container {} labeled", &add_args.name);
+    let _ = Msg::OkContent(format!("Container {} labeled", &add_args.name))
+        .send_to(&mut stream)
+        .await;
+}
+
+/// Remove labels from a container, all the keys must be present.
+pub async fn remove_labels(rm_args: LabelRmArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+    let _updating = LABEL_UPDATES.lock().await;
+
+    let Some(meta) = container_metas.get_meta_by_name(&rm_args.name).await else {
+        error!(
+            "Failed to unlabel container {}, record does not exist",
+            &rm_args.name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to unlabel container {}, record does not exist",
+            &rm_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    };
+
+    let mut labels = meta.labels;
+    for key in &rm_args.keys {
+        if labels.remove(key).is_none() {
+            error!(
+                "Failed to unlabel container {}, no label {}",
+                &rm_args.name, key
+            );
+            let _ = Msg::Err(format!(
+                "Failed to unlabel container {}, no label {}",
+                &rm_args.name, key
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    }
+
+    if let Err(e) = container_metas.update_labels(meta.id, labels).await {
+        error!("Failed to update container labels: {:?}", e);
+        let _ = Msg::Err(format!("Failed to update container labels: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    info!("[Daemon] Container {} unlabeled", &rm_args.name);
+    let _ = Msg::OkContent(format!("Container {} unlabeled", &rm_args.name))
+        .send_to(&mut stream)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label_lines() {
+        let labels =
+            parse_label_lines("# team\nowner=alice\n\n  beta  \nurl=http://x/?a=b\n").unwrap();
+        assert_eq!(
+            labels,
+            vec![
+                ("owner".to_string(), "alice".to_string()),
+                ("beta".to_string(), String::new()),
+                ("url".to_string(), "http://x/?a=b".to_string()),
+            ]
+        );
+
+        assert!(parse_label_lines("=value").is_err());
+    }
+}
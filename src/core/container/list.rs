@@ -5,42 +5,176 @@ use log::error;
 use tabwriter::TabWriter;
 use tokio::net::UnixStream;
 
-use crate::core::cmd::{LogsArgs, PSArgs};
-use crate::core::metas::CONTAINER_METAS;
+use crate::core::cmd::{LogsArgs, PSArgs, PSFormat};
+use crate::core::metas::{ContainerFilter, ContainerMeta, CONTAINER_METAS};
 use crate::core::{Msg, ROOT_PATH};
 
-pub async fn list_containers(_ps_args: PSArgs, mut stream: UnixStream) {
-    let metas = CONTAINER_METAS.get().unwrap().get_all_metas().await;
+/// List containers, hiding exited ones unless asked for all of them.
+pub async fn list_containers(ps_args: PSArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
 
+    let (filter, since) = match build_filter(&ps_args).await {
+        Ok(filter) => filter,
+        Err(e) => {
+            error!("Failed to list containers: {:?}", e);
+            let _ = Msg::Err(format!("Failed to list containers: {}", e))
+                .send_to(&mut stream)
+                .await;
+
+            return;
+        }
+    };
+
+    let show_all = shows_all(&ps_args, &filter);
+
+    // Listed newest first, so those created after the reference come before it.
+    let metas: Vec<_> = container_metas
+        .list_containers(Some(filter))
+        .await
+        .into_iter()
+        .filter(|meta| show_all || !meta.state.status.is_stopped())
+        .filter(|meta| {
+            since
+                .as_ref()
+                .is_none_or(|since| created_after(meta, since))
+        })
+        .take(ps_args.last.unwrap_or(usize::MAX))
+        .collect();
+
+    let output = if ps_args.quiet {
+        Ok(metas
+            .iter()
+            .map(|meta| meta.id.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    } else {
+        match ps_args.format {
+            PSFormat::Table => format_table(&metas),
+            PSFormat::Json => serde_json::to_string_pretty(&metas).map_err(anyhow::Error::from),
+        }
+    };
+
+    match output {
+        Ok(output) => {
+            let _ = Msg::OkContent(output).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!("Failed to format containers: {}", e);
+
+            let _ = Msg::Err(format!("Failed to format containers: {}", e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+/// Whether exited containers are listed too. Asking for a status, the latest containers or those
+/// created after one implies all of them.
+fn shows_all(ps_args: &PSArgs, filter: &ContainerFilter) -> bool {
+    ps_args.all || ps_args.last.is_some() || ps_args.since.is_some() || filter.status.is_some()
+}
+
+/// Turn the `ps` options into a container filter, and the container to list those created after,
+/// if any. Both that and the limit go by the listing order, so they are applied to the listing.
+async fn build_filter(
+    ps_args: &PSArgs,
+) -> anyhow::Result<(ContainerFilter, Option<ContainerMeta>)> {
+    let filter = filter_from(&ps_args.filters)?;
+
+    let since = match &ps_args.since {
+        Some(since) => Some(
+            find_meta(since)
+                .await
+                .ok_or(anyhow::anyhow!("Container {since} does not exist"))?,
+        ),
+        None => None,
+    };
+
+    Ok((filter, since))
+}
+
+/// Whether `meta` is listed before `reference`, newest first. Containers of records without the
+/// creation time to the nanosecond may be created within the same second, those are ordered by
+/// name, as in the listing.
+fn created_after(meta: &ContainerMeta, reference: &ContainerMeta) -> bool {
+    meta.creation() > reference.creation()
+        || (meta.creation() == reference.creation() && meta.name < reference.name)
+}
+
+/// Turn `key=value` filters into a container filter.
+pub(super) fn filter_from(filters: &[(String, String)]) -> anyhow::Result<ContainerFilter> {
+    let mut filter = ContainerFilter::default();
+
+    for (key, value) in filters {
+        match key.as_str() {
+            "status" => {
+                if filter.status.is_some() {
+                    return Err(anyhow::anyhow!("Only one status filter is supported"));
+                }
+                filter.status = Some(value.parse().map_err(anyhow::Error::msg)?);
+            }
+            "label" => {
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or(anyhow::anyhow!("Invalid label filter: {value}"))?;
+                filter.labels.insert(key.to_string(), value.to_string());
+            }
+            "name" => {
+                if filter.name_pattern.is_some() {
+                    return Err(anyhow::anyhow!("Only one name filter is supported"));
+                }
+                filter.name_pattern = Some(value.clone());
+            }
+            _ => return Err(anyhow::anyhow!("Invalid filter: {key}")),
+        }
+    }
+
+    Ok(filter)
+}
+
+async fn find_meta(name_or_id: &str) -> Option<ContainerMeta> {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    match container_metas.get_meta_by_name(name_or_id).await {
+        Some(meta) => Some(meta),
+        None => container_metas.get_meta_by_id(name_or_id).await,
+    }
+}
+
+fn format_table(metas: &[ContainerMeta]) -> anyhow::Result<String> {
     let mut tw = TabWriter::new(vec![]);
-    let _ = tw.write_all(b"ID\tNAME\tPID\tCOMMAND\tSTATUS\n");
+    let _ = tw.write_all(b"ID\tNAME\tPID\tCOMMAND\tSTATUS\tLABELS\n");
 
     for meta in metas {
+        // Health is only shown for containers which have a health check.
+        let status = match (&meta.state.exit_code, &meta.health_check) {
+            (Some(code), _) if meta.state.status.is_stopped() => {
+                format!("{:?} ({})", meta.state.status, code)
+            }
+            (_, Some(_)) => format!("{:?} ({:?})", meta.state.status, meta.state.health_status),
+            _ => format!("{:?}", meta.state.status),
+        };
+
+        let mut labels: Vec<_> = meta
+            .labels
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        labels.sort();
+
         let _ = writeln!(
             tw,
-            "{}\t{}\t{}\t{}\t{:?}",
+            "{}\t{}\t{}\t{}\t{}\t{}",
             meta.id,
             meta.name,
             meta.get_pid().unwrap_or(0),
-            meta.command.join(" "),
-            meta.state.status
+            meta.command_line().join(" "),
+            status,
+            labels.join(",")
         );
     }
 
-    match tw.into_inner() {
-        Ok(data) => {
-            let _ = Msg::OkContent(String::from_utf8(data).unwrap())
-                .send_to(&mut stream)
-                .await;
-        }
-        Err(e) => {
-            error!("Failed to write to tab writer: {}", e);
-
-            let _ = Msg::Err(format!("Failed to write to tab writer: {}", e))
-                .send_to(&mut stream)
-                .await;
-        }
-    }
+    Ok(String::from_utf8(tw.into_inner()?)?)
 }
 
 pub async fn show_logs(log_args: LogsArgs, mut stream: UnixStream) {
@@ -86,3 +220,69 @@ pub async fn show_logs(log_args: LogsArgs, mut stream: UnixStream) {
 
     let _ = Msg::OkContent(logs).send_to(&mut stream).await;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::core::metas::ContainerStatus;
+
+    use super::*;
+
+    fn meta(name: &str, created_at: u64, created_at_nanos: u32) -> ContainerMeta {
+        let mut meta = ContainerMeta::new(
+            format!("{name}-id"),
+            name.to_string(),
+            "busybox".to_string(),
+            vec!["sh".to_string()],
+            vec![],
+        );
+        meta.created_at = created_at;
+        meta.created_at_nanos = created_at_nanos;
+        meta
+    }
+
+    #[test]
+    fn test_shows_all() {
+        let filter = ContainerFilter::default();
+        assert!(!shows_all(&PSArgs::default(), &filter));
+
+        for ps_args in [
+            PSArgs {
+                all: true,
+                ..Default::default()
+            },
+            PSArgs {
+                last: Some(1),
+                ..Default::default()
+            },
+            PSArgs {
+                since: Some("web".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(shows_all(&ps_args, &filter));
+        }
+
+        let filter = ContainerFilter {
+            status: Some(ContainerStatus::Exited),
+            ..Default::default()
+        };
+        assert!(shows_all(&PSArgs::default(), &filter));
+    }
+
+    #[test]
+    fn test_created_after() {
+        let reference = meta("b", 100, 500);
+
+        assert!(created_after(&meta("a", 101, 0), &reference));
+        assert!(!created_after(&meta("c", 99, 900), &reference));
+        assert!(!created_after(&reference, &reference));
+
+        // Within the same second.
+        assert!(created_after(&meta("a", 100, 600), &reference));
+        assert!(!created_after(&meta("a", 100, 400), &reference));
+
+        // Without the nanoseconds, those listed before the reference.
+        assert!(created_after(&meta("a", 100, 500), &reference));
+        assert!(!created_after(&meta("c", 100, 500), &reference));
+    }
+}
@@ -1,16 +1,34 @@
+mod bulk;
 mod commit;
 mod exec;
+mod health;
 mod image;
 mod init;
+mod inspect;
+mod kill;
+mod label;
 mod list;
+mod pause;
+mod process;
+mod prune;
+mod resource;
+mod restart;
 mod rm;
 mod start;
 mod stop;
+mod update;
 
 pub use commit::commit_container;
 pub use exec::exec_container;
-pub use init::run_container;
+pub use init::{random_id, run_container};
+pub use inspect::inspect_container;
+pub use kill::kill_container;
+pub use label::{add_labels, remove_labels};
 pub use list::{list_containers, show_logs};
+pub use pause::{pause_container, unpause_container};
+pub use prune::{format_size, prune_containers, prune_networks, prune_system};
+pub use restart::restore_containers;
 pub use rm::remove_container;
 pub use start::start_container;
 pub use stop::stop_container;
+pub use update::update_container;
@@ -0,0 +1,168 @@
+use cgroups_rs::{freezer::FreezerController, Cgroup};
+use log::{error, info};
+use tokio::net::UnixStream;
+
+use crate::core::{
+    cmd::{PauseArgs, UnpauseArgs},
+    metas::{ContainerStatus, CONTAINER_METAS},
+    Msg,
+};
+
+/// Freeze all processes of a running container.
+pub async fn pause_container(pause_args: PauseArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&pause_args.name).await {
+        Some(meta) => meta,
+        None => {
+            error!(
+                "Failed to pause container {}, record does not exist",
+                &pause_args.name
+            );
+            let _ = Msg::Err(format!(
+                "Failed to pause container {}, record does not exist",
+                &pause_args.name
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if !meta.state.status.is_running() {
+        error!(
+            "Failed to pause container {}, it's not running",
+            &pause_args.name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to pause container {}, it's not running",
+            &pause_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    let name_id = format!("{}-{}", meta.name, meta.id);
+    if let Err(e) = set_frozen(&name_id, true) {
+        error!("Failed to pause container {}: {:?}", &pause_args.name, e);
+        let _ = Msg::Err(format!(
+            "Failed to pause container {}: {}",
+            &pause_args.name, e
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    if let Err(e) = container_metas
+        .updates(meta.id, ContainerStatus::Paused)
+        .await
+    {
+        let _ = set_frozen(&name_id, false);
+
+        error!("Failed to update container status: {:?}", e);
+        let _ = Msg::Err(format!("Failed to update container status: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    info!("[Daemon] Container {} paused", &pause_args.name);
+    let _ = Msg::OkContent(format!("Container {} paused", &pause_args.name))
+        .send_to(&mut stream)
+        .await;
+}
+
+/// Resume a paused container.
+pub async fn unpause_container(unpause_args: UnpauseArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&unpause_args.name).await {
+        Some(meta) => meta,
+        None => {
+            error!(
+                "Failed to unpause container {}, record does not exist",
+                &unpause_args.name
+            );
+            let _ = Msg::Err(format!(
+                "Failed to unpause container {}, record does not exist",
+                &unpause_args.name
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if meta.state.status != ContainerStatus::Paused {
+        error!(
+            "Failed to unpause container {}, it's not paused",
+            &unpause_args.name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to unpause container {}, it's not paused",
+            &unpause_args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    let name_id = format!("{}-{}", meta.name, meta.id);
+    if let Err(e) = set_frozen(&name_id, false) {
+        error!(
+            "Failed to unpause container {}: {:?}",
+            &unpause_args.name, e
+        );
+        let _ = Msg::Err(format!(
+            "Failed to unpause container {}: {}",
+            &unpause_args.name, e
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    if let Err(e) = container_metas
+        .updates(meta.id, ContainerStatus::Running)
+        .await
+    {
+        error!("Failed to update container status: {:?}", e);
+        let _ = Msg::Err(format!("Failed to update container status: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    info!("[Daemon] Container {} unpaused", &unpause_args.name);
+    let _ = Msg::OkContent(format!("Container {} unpaused", &unpause_args.name))
+        .send_to(&mut stream)
+        .await;
+}
+
+/// Freeze or thaw the cgroup of a container, with the v1 freezer or the v2 `cgroup.freeze`.
+pub fn set_frozen(name_id: &str, frozen: bool) -> anyhow::Result<()> {
+    let hier = cgroups_rs::hierarchies::auto();
+    let cg = Cgroup::load(hier, name_id);
+
+    let freezer: &FreezerController = cg
+        .controller_of()
+        .ok_or(anyhow::anyhow!("Freezer controller not available"))?;
+
+    let res = if frozen {
+        freezer.freeze()
+    } else {
+        freezer.thaw()
+    };
+
+    res.map_err(|e| anyhow::anyhow!("Failed to set cgroup freezer: {:?}", e))
+}
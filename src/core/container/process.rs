@@ -0,0 +1,337 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::CString,
+    fs::{read_to_string, OpenOptions},
+    io::{ErrorKind, Read},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use nix::{
+    libc,
+    unistd::{access, chdir, execve, setgid, setgroups, setuid, AccessFlags, Gid, Uid},
+};
+
+use super::image::resolve_in_root;
+
+use crate::core::cmd::ProcessArgs;
+
+/// Search path of commands when the container does not set `PATH`.
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// How a command is run in a container, resolved by the daemon before the process is created,
+/// as the process can hardly report errors.
+#[derive(Debug, Clone)]
+pub struct ProcessConfig {
+    env: Vec<CString>,
+    path: String,
+    working_dir: String,
+    user: Option<User>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct User {
+    uid: u32,
+    gid: u32,
+    groups: Vec<u32>,
+    home: Option<String>,
+}
+
+impl ProcessConfig {
+    /// Resolve the config of a command, `rootfs` is the container root seen from the daemon, where
+    /// users are looked up.
+    pub fn resolve(
+        rootfs: &Path,
+        env: &HashMap<String, String>,
+        working_dir: Option<&str>,
+        user: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let working_dir = working_dir.unwrap_or("/");
+        if !working_dir.starts_with('/') {
+            return Err(anyhow::anyhow!(
+                "Working directory {working_dir} must be absolute"
+            ));
+        }
+
+        let user = match user {
+            Some(spec) => Some(resolve_user(
+                &read_in_root(rootfs, "/etc/passwd")?,
+                &read_in_root(rootfs, "/etc/group")?,
+                spec,
+            )?),
+            None => None,
+        };
+
+        let home = user
+            .as_ref()
+            .and_then(|user| user.home.clone())
+            .unwrap_or_else(|| "/root".to_string());
+        let mut vars = BTreeMap::from([
+            ("PATH".to_string(), DEFAULT_PATH.to_string()),
+            ("HOME".to_string(), home),
+        ]);
+        vars.extend(env.iter().map(|(key, value)| (key.clone(), value.clone())));
+
+        Ok(Self {
+            env: vars
+                .iter()
+                .map(|(key, value)| CString::new(format!("{key}={value}")))
+                .collect::<Result<_, _>>()?,
+            path: vars["PATH"].clone(),
+            working_dir: working_dir.to_string(),
+            user,
+        })
+    }
+
+    /// Replace the current process with `command`, in the working directory and as the user of
+    /// the config. Runs within the container, and only returns on failure.
+    pub fn exec(&self, command: &[String]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.working_dir)?;
+        chdir(self.working_dir.as_str())?;
+
+        if let Some(user) = &self.user {
+            let groups: Vec<_> = user.groups.iter().map(|gid| Gid::from_raw(*gid)).collect();
+            setgroups(&groups)?;
+            setgid(Gid::from_raw(user.gid))?;
+            setuid(Uid::from_raw(user.uid))?;
+        }
+
+        let program = self.find_program(&command[0])?;
+        let args = command
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        execve(&program, &args, &self.env)?;
+
+        Ok(())
+    }
+
+    /// Look up `name` in the container `PATH`, as the daemon's own does not apply.
+    fn find_program(&self, name: &str) -> anyhow::Result<CString> {
+        if name.contains('/') {
+            return Ok(CString::new(name)?);
+        }
+
+        self.path
+            .split(':')
+            .map(|dir| match dir {
+                "" => format!("./{name}"),
+                dir => format!("{dir}/{name}"),
+            })
+            .find(|path| {
+                Path::new(path).is_file() && access(path.as_str(), AccessFlags::X_OK).is_ok()
+            })
+            .map(CString::new)
+            .ok_or(anyhow::anyhow!("{name}: executable file not found in PATH"))?
+            .map_err(anyhow::Error::from)
+    }
+}
+
+/// Environment of a container or command, `base` updated by the env files and variables given.
+pub fn merge_env(
+    base: &HashMap<String, String>,
+    args: &ProcessArgs,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut env = base.clone();
+
+    for path in &args.env_file {
+        let content = read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read env file {path}: {e}"))?;
+        env.extend(parse_env_lines(&content)?);
+    }
+    env.extend(args.env.iter().cloned());
+
+    Ok(env)
+}
+
+/// Parse the content of an env file, skipping blank lines and comments.
+pub(super) fn parse_env_lines(content: &str) -> anyhow::Result<Vec<(String, String)>> {
+    content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| match line.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+            _ => Err(anyhow::anyhow!("Invalid env file line {}: {}", n + 1, line)),
+        })
+        .collect()
+}
+
+/// Read the file at `path` of the container, with `rootfs` as its root, so that symlinks of the
+/// image do not lead to files of the host. A missing file reads as empty.
+fn read_in_root(rootfs: &Path, path: &str) -> anyhow::Result<String> {
+    let resolved = resolve_in_root(rootfs, path)?;
+
+    // Symlinks are all resolved by now, one found here was swapped in and may lead out.
+    let mut file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&resolved)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(String::new()),
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "Failed to read {path} of the container: {e}"
+            ))
+        }
+    };
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    Ok(content)
+}
+
+/// Resolve `user[:group]`, each by name or ID, against the `passwd` and `group` files of the
+/// image. A numeric user missing from `passwd` runs with group 0, as in Docker.
+fn resolve_user(passwd: &str, group: &str, spec: &str) -> anyhow::Result<User> {
+    let (user_spec, group_spec) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+
+    let (mut user, name) = match find_entry(passwd, user_spec) {
+        Some(fields) if fields.len() >= 6 => (
+            User {
+                uid: fields[2].parse()?,
+                gid: fields[3].parse()?,
+                groups: vec![],
+                home: Some(fields[5].to_string()),
+            },
+            Some(fields[0]),
+        ),
+        _ => match user_spec.parse() {
+            Ok(uid) => (
+                User {
+                    uid,
+                    gid: 0,
+                    groups: vec![],
+                    home: None,
+                },
+                None,
+            ),
+            Err(_) => return Err(anyhow::anyhow!("User {user_spec} not found in the image")),
+        },
+    };
+
+    if let Some(group_spec) = group_spec {
+        user.gid = match find_entry(group, group_spec) {
+            Some(fields) if fields.len() >= 3 => fields[2].parse()?,
+            _ => group_spec
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Group {group_spec} not found in the image"))?,
+        };
+    }
+
+    // Supplementary groups only apply to a named user.
+    if let Some(name) = name {
+        for line in group.lines() {
+            let fields: Vec<_> = line.split(':').collect();
+            if fields.len() >= 4 && fields[3].split(',').any(|member| member == name) {
+                user.groups.push(fields[2].parse()?);
+            }
+        }
+    }
+    if !user.groups.contains(&user.gid) {
+        user.groups.insert(0, user.gid);
+    }
+
+    Ok(user)
+}
+
+/// Entry of a `passwd` or `group` file, by name or by ID.
+fn find_entry<'a>(content: &'a str, key: &str) -> Option<Vec<&'a str>> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields[0] == key || fields.get(2) == Some(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWD: &str =
+        "root:x:0:0:root:/root:/bin/sh\nnginx:x:101:101:nginx:/var/cache/nginx:/sbin/nologin\n";
+    const GROUP: &str = "root:x:0:\nnginx:x:101:\nwww:x:33:nginx,other\n";
+
+    #[test]
+    fn test_resolve_user() {
+        let user = resolve_user(PASSWD, GROUP, "nginx").unwrap();
+        assert_eq!((user.uid, user.gid), (101, 101));
+        assert_eq!(user.groups, vec![101, 33]);
+        assert_eq!(user.home.as_deref(), Some("/var/cache/nginx"));
+
+        let user = resolve_user(PASSWD, GROUP, "101:www").unwrap();
+        assert_eq!((user.uid, user.gid), (101, 33));
+        assert_eq!(user.groups, vec![33]);
+
+        let user = resolve_user(PASSWD, GROUP, "1000:1000").unwrap();
+        assert_eq!((user.uid, user.gid), (1000, 1000));
+        assert_eq!(user.home, None);
+
+        let user = resolve_user(PASSWD, GROUP, "1000").unwrap();
+        assert_eq!(user.gid, 0);
+
+        assert!(resolve_user(PASSWD, GROUP, "nobody").is_err());
+        assert!(resolve_user(PASSWD, GROUP, "nginx:nogroup").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_lines() {
+        let env = parse_env_lines("# comment\n\nA=1\n  B=x=y  \nC=\n").unwrap();
+        assert_eq!(
+            env,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "x=y".to_string()),
+                ("C".to_string(), String::new()),
+            ]
+        );
+
+        assert!(parse_env_lines("A=1\nB\n").is_err());
+        assert!(parse_env_lines("=1\n").is_err());
+    }
+
+    #[test]
+    fn test_read_in_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path();
+        std::fs::create_dir_all(rootfs.join("etc")).unwrap();
+        std::fs::write(rootfs.join("etc/users"), "app:x:1000:1000::/app:/bin/sh\n").unwrap();
+        std::os::unix::fs::symlink("/etc/users", rootfs.join("etc/passwd")).unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", rootfs.join("etc/group")).unwrap();
+
+        // Absolute symlinks lead within the rootfs, not to the files of the host.
+        assert_eq!(
+            read_in_root(rootfs, "/etc/passwd").unwrap(),
+            "app:x:1000:1000::/app:/bin/sh\n"
+        );
+        assert_eq!(read_in_root(rootfs, "/etc/group").unwrap(), "");
+
+        let config = ProcessConfig::resolve(rootfs, &HashMap::new(), None, Some("app")).unwrap();
+        assert_eq!(config.user.unwrap().uid, 1000);
+        assert!(ProcessConfig::resolve(rootfs, &HashMap::new(), None, Some("root")).is_err());
+    }
+
+    #[test]
+    fn test_resolve_env() {
+        let env = HashMap::from([("PATH".to_string(), "/app/bin".to_string())]);
+        let config = ProcessConfig::resolve(Path::new("/nonexistent"), &env, None, None).unwrap();
+
+        assert_eq!(config.path, "/app/bin");
+        assert_eq!(config.working_dir, "/");
+        assert_eq!(
+            config.env,
+            vec![
+                CString::new("HOME=/root").unwrap(),
+                CString::new("PATH=/app/bin").unwrap(),
+            ]
+        );
+
+        assert!(ProcessConfig::resolve(Path::new("/"), &env, Some("app"), None).is_err());
+    }
+}
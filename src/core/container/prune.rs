@@ -0,0 +1,417 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{read_dir, symlink_metadata},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use cgroups_rs::Cgroup;
+use futures::future::join_all;
+use log::{error, info};
+use tokio::net::UnixStream;
+
+use crate::core::{
+    cmd::PruneArgs,
+    metas::{current_time, ContainerFilter, CONTAINER_METAS},
+    network::remove_unused_networks,
+    Msg, ROOT_PATH,
+};
+
+use super::image::umount_all;
+use super::rm::remove_one;
+
+/// Age under which a workspace or cgroup without a record is not a leftover, as a container being
+/// run has them before it is registered.
+const LEFTOVER_GRACE: u64 = 10 * 60;
+
+/// What a prune removed, reported to the client.
+#[derive(Debug, Default)]
+struct PruneReport {
+    containers: Vec<String>,
+    networks: Vec<String>,
+    leftovers: Vec<String>,
+    reclaimed: u64,
+    errors: Vec<String>,
+}
+
+/// Remove all stopped containers.
+pub async fn prune_containers(prune_args: PruneArgs, mut stream: UnixStream) {
+    let Some(filter) = parse_filter("containers", &prune_args, &mut stream).await else {
+        return;
+    };
+
+    let mut report = PruneReport::default();
+    remove_stopped_containers(&filter, &mut report).await;
+
+    report.send_to(&mut stream).await;
+}
+
+/// Remove all networks without attached containers.
+pub async fn prune_networks(prune_args: PruneArgs, mut stream: UnixStream) {
+    let Some(filter) = parse_filter("networks", &prune_args, &mut stream).await else {
+        return;
+    };
+
+    let mut report = PruneReport::default();
+    (report.networks, report.errors) = remove_unused_networks(&filter).await;
+
+    report.send_to(&mut stream).await;
+}
+
+/// Remove stopped containers, unused networks, and the workspaces and cgroups of containers
+/// without a record.
+pub async fn prune_system(prune_args: PruneArgs, mut stream: UnixStream) {
+    let Some(filter) = parse_filter("system", &prune_args, &mut stream).await else {
+        return;
+    };
+
+    // Containers go first, so that their networks become unused.
+    let mut report = PruneReport::default();
+    remove_stopped_containers(&filter, &mut report).await;
+
+    let (networks, errors) = remove_unused_networks(&filter).await;
+    report.networks = networks;
+    report.errors.extend(errors);
+
+    remove_leftovers(&filter, &mut report).await;
+
+    report.send_to(&mut stream).await;
+}
+
+async fn parse_filter(
+    target: &str,
+    prune_args: &PruneArgs,
+    stream: &mut UnixStream,
+) -> Option<ContainerFilter> {
+    match prune_filter(&prune_args.filters) {
+        Ok(filter) => Some(filter),
+        Err(e) => {
+            error!("Failed to prune {}: {:?}", target, e);
+            let _ = Msg::Err(format!("Failed to prune {}: {}", target, e))
+                .send_to(stream)
+                .await;
+
+            None
+        }
+    }
+}
+
+/// Turn the `until` and `label` filters of prune into a container filter.
+fn prune_filter(filters: &[(String, String)]) -> anyhow::Result<ContainerFilter> {
+    let mut filter = ContainerFilter::default();
+
+    for (key, value) in filters {
+        match key.as_str() {
+            "until" => {
+                if filter.until.is_some() {
+                    return Err(anyhow::anyhow!("Only one until filter is supported"));
+                }
+                let age: u64 = value.parse()?;
+                filter.until = Some(current_time().saturating_sub(age));
+            }
+            "label" => {
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or(anyhow::anyhow!("Invalid label filter: {value}"))?;
+                filter.labels.insert(key.to_string(), value.to_string());
+            }
+            _ => return Err(anyhow::anyhow!("Invalid filter: {key}")),
+        }
+    }
+
+    Ok(filter)
+}
+
+async fn remove_stopped_containers(filter: &ContainerFilter, report: &mut PruneReport) {
+    let stopped: Vec<_> = CONTAINER_METAS
+        .get()
+        .unwrap()
+        .get_all_metas()
+        .await
+        .into_iter()
+        .filter(|meta| meta.state.status.is_stopped() && filter.matches(meta))
+        .collect();
+
+    // Measured before removal, as nothing is left to measure after.
+    let sizes: Vec<_> = stopped
+        .iter()
+        .map(|meta| {
+            disk_usage(Path::new(&format!(
+                "{}/{}-{}",
+                ROOT_PATH, meta.name, meta.id
+            )))
+        })
+        .collect();
+    let results = join_all(
+        stopped
+            .iter()
+            .map(|meta| remove_one(meta.name.clone(), false, false)),
+    )
+    .await;
+
+    for ((meta, size), result) in stopped.into_iter().zip(sizes).zip(results) {
+        match result {
+            Ok(_) => {
+                report.containers.push(meta.name);
+                report.reclaimed += size;
+            }
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+}
+
+/// Remove the workspaces and cgroups of containers without a record, left behind by a failed
+/// run or removal. They carry no labels, so none matches a label filter.
+async fn remove_leftovers(filter: &ContainerFilter, report: &mut PruneReport) {
+    if !filter.labels.is_empty() {
+        return;
+    }
+
+    let known: HashSet<_> = CONTAINER_METAS
+        .get()
+        .unwrap()
+        .get_all_metas()
+        .await
+        .into_iter()
+        .map(|meta| meta.id)
+        .collect();
+
+    // Leftovers by `<name>-<id>`, with when they were last modified.
+    let mut leftovers = BTreeMap::new();
+    let workspaces = subdirs(&[PathBuf::from(ROOT_PATH)]);
+    for (dir, path) in workspaces.into_iter().chain(cgroup_dirs()) {
+        if container_id(&dir).is_some_and(|id| !known.contains(id)) {
+            let modified = leftovers.entry(dir).or_insert(u64::MAX);
+            *modified = (*modified).min(modified_at(&path));
+        }
+    }
+
+    let settled = current_time().saturating_sub(LEFTOVER_GRACE);
+    for (name_id, modified) in leftovers {
+        if modified > settled || filter.until.is_some_and(|until| modified > until) {
+            continue;
+        }
+
+        // A process still running there is not ours to remove.
+        let cg = Cgroup::load(cgroups_rs::hierarchies::auto(), name_id.as_str());
+        if !cg.procs().is_empty() {
+            continue;
+        }
+
+        let root_path = Path::new(ROOT_PATH).join(&name_id);
+        if root_path.exists() {
+            let size = disk_usage(&root_path);
+
+            // The rootfs and volumes must be gone, or their contents would be deleted too.
+            if let Err(e) = umount_all(&root_path) {
+                error!("Failed to unmount workspace {:?}: {}", root_path, e);
+                report
+                    .errors
+                    .push(format!("Failed to unmount workspace {name_id}: {e}"));
+                continue;
+            }
+            if let Err(e) = tokio::fs::remove_dir_all(&root_path).await {
+                error!("Failed to remove workspace {:?}: {}", root_path, e);
+                report
+                    .errors
+                    .push(format!("Failed to remove workspace {name_id}: {e}"));
+                continue;
+            }
+            report.reclaimed += size;
+        }
+
+        if let Err(e) = cg.delete() {
+            error!("Failed to remove cgroup {}: {}", name_id, e);
+            report
+                .errors
+                .push(format!("Failed to remove cgroup {name_id}: {e}"));
+            continue;
+        }
+
+        report.leftovers.push(name_id);
+    }
+}
+
+/// Directories of all container cgroups, which live at the top of the hierarchy, under each
+/// controller with cgroup v1.
+fn cgroup_dirs() -> Vec<(String, PathBuf)> {
+    let hier = cgroups_rs::hierarchies::auto();
+
+    if hier.v2() {
+        subdirs(&[hier.root()])
+    } else {
+        let controllers: Vec<_> = subdirs(&[hier.root()])
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        subdirs(&controllers)
+    }
+}
+
+/// Names and paths of the directories right under `dirs`.
+fn subdirs(dirs: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    dirs.iter()
+        .filter_map(|dir| read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|ty| ty.is_dir()))
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .collect()
+}
+
+/// ID of the container a workspace or cgroup named `<name>-<id>` belongs to.
+fn container_id(name_id: &str) -> Option<&str> {
+    let (_, id) = name_id.rsplit_once('-')?;
+
+    let is_id = id.len() == 32
+        && id
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+    is_id.then_some(id)
+}
+
+fn modified_at(path: &Path) -> u64 {
+    symlink_metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Disk space used under `path`, not counting other filesystems mounted within, such as the
+/// rootfs of a container.
+fn disk_usage(path: &Path) -> u64 {
+    match symlink_metadata(path) {
+        Ok(meta) => usage_on(path, meta.dev()),
+        Err(_) => 0,
+    }
+}
+
+fn usage_on(path: &Path, dev: u64) -> u64 {
+    let Ok(meta) = symlink_metadata(path) else {
+        return 0;
+    };
+    if meta.dev() != dev {
+        return 0;
+    }
+
+    let mut usage = meta.blocks() * 512;
+    if meta.is_dir() {
+        for entry in read_dir(path).into_iter().flatten().flatten() {
+            usage += usage_on(&entry.path(), dev);
+        }
+    }
+
+    usage
+}
+
+/// Size in decimal units, as `12.3MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{size:.1}{}", UNITS[unit])
+    }
+}
+
+impl PruneReport {
+    fn render(&self) -> String {
+        let mut sections = vec![];
+
+        for (title, names) in [
+            ("Deleted containers:", &self.containers),
+            ("Deleted networks:", &self.networks),
+            ("Deleted leftovers of removed containers:", &self.leftovers),
+        ] {
+            if !names.is_empty() {
+                sections.push(format!("{title}\n{}", names.join("\n")));
+            }
+        }
+        sections.push(format!(
+            "Total reclaimed space: {}",
+            format_size(self.reclaimed)
+        ));
+        if !self.errors.is_empty() {
+            sections.push(self.errors.join("\n"));
+        }
+
+        sections.join("\n\n")
+    }
+
+    /// Reply with the report, as an error if anything failed to be removed.
+    async fn send_to(self, stream: &mut UnixStream) {
+        info!(
+            "[Daemon] Pruned {} containers, {} networks, {} leftovers, {} reclaimed",
+            self.containers.len(),
+            self.networks.len(),
+            self.leftovers.len(),
+            format_size(self.reclaimed)
+        );
+
+        let msg = if self.errors.is_empty() {
+            Msg::OkContent(self.render())
+        } else {
+            Msg::Err(self.render())
+        };
+        let _ = msg.send_to(stream).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_id() {
+        let id = "0123456789abcdef0123456789abcdef";
+
+        assert_eq!(container_id(&format!("web-{id}")), Some(id));
+        assert_eq!(container_id(&format!("my-web-{id}")), Some(id));
+        assert_eq!(container_id(id), None);
+        assert_eq!(container_id("containermetas"), None);
+        assert_eq!(container_id("user-1000.slice"), None);
+        assert_eq!(container_id(&format!("web-{}", id.to_uppercase())), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(999), "999B");
+        assert_eq!(format_size(1500), "1.5kB");
+        assert_eq!(format_size(12_300_000), "12.3MB");
+    }
+
+    #[test]
+    fn test_prune_filter() {
+        let filter = prune_filter(&[
+            ("until".to_string(), "3600".to_string()),
+            ("label".to_string(), "app=web".to_string()),
+        ])
+        .unwrap();
+
+        assert!(filter.until.unwrap() <= current_time() - 3600);
+        assert_eq!(filter.labels["app"], "web");
+
+        assert!(prune_filter(&[
+            ("until".to_string(), "1".to_string()),
+            ("until".to_string(), "2".to_string()),
+        ])
+        .is_err());
+        assert!(prune_filter(&[("status".to_string(), "exited".to_string())]).is_err());
+    }
+}
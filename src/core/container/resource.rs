@@ -0,0 +1,179 @@
+use cgroups_rs::{Cgroup, CpuResources, MaxValue, MemoryResources, PidResources, Resources};
+
+use crate::core::{cmd::ResourceArgs, metas::ResourceConfig};
+
+/// CFS period used to turn `--cpus` into a quota, in microseconds.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// Merge the limits given on the command line into `base`, limits not given are kept.
+pub fn merge_resources(
+    base: &ResourceConfig,
+    args: &ResourceArgs,
+) -> anyhow::Result<ResourceConfig> {
+    let mut config = base.clone();
+
+    if let Some(memory) = args.memory {
+        if memory <= 0 {
+            return Err(anyhow::anyhow!("Invalid memory limit: {memory}"));
+        }
+        config.memory_limit = Some(memory as u64);
+    }
+
+    if let Some(memory_swap) = args.memory_swap {
+        if memory_swap <= 0 {
+            return Err(anyhow::anyhow!("Invalid memory swap limit: {memory_swap}"));
+        }
+        config.memory_swap_limit = Some(memory_swap as u64);
+    }
+
+    if let Some(cpus) = args.cpus {
+        if !cpus.is_finite() || cpus <= 0.0 {
+            return Err(anyhow::anyhow!("Invalid cpus: {cpus}"));
+        }
+        config.cpu_limit = Some(cpus);
+    }
+
+    if let Some(pids_limit) = args.pids_limit {
+        if pids_limit == 0 {
+            return Err(anyhow::anyhow!("Invalid pids limit: {pids_limit}"));
+        }
+        config.pids_limit = Some(pids_limit);
+    }
+
+    if let Some(memory_swap) = config.memory_swap_limit {
+        match config.memory_limit {
+            Some(memory) if memory_swap < memory => {
+                return Err(anyhow::anyhow!(
+                    "Memory swap limit {memory_swap} is less than memory limit {memory}"
+                ));
+            }
+            None => {
+                return Err(anyhow::anyhow!(
+                    "Memory swap limit requires a memory limit to be set"
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(config)
+}
+
+/// Write the resource limits into the container's cgroup.
+pub fn apply_resources(cg: &Cgroup, config: &ResourceConfig) -> anyhow::Result<()> {
+    cg.apply(&cgroup_resources(config, cg.v2()))
+        .map_err(|e| anyhow::anyhow!("Failed to apply resources to cgroup: {:?}", e))
+}
+
+fn cgroup_resources(config: &ResourceConfig, v2: bool) -> Resources {
+    // Cgroup v1 limits memory plus swap, while v2 only limits the swap part.
+    let memory_swap_limit = config
+        .memory_swap_limit
+        .map(|swap| match config.memory_limit {
+            Some(memory) if v2 => swap.saturating_sub(memory) as i64,
+            _ => swap as i64,
+        });
+
+    let (quota, period) = match config.cpu_limit {
+        Some(cpus) => (
+            Some((cpus * CPU_PERIOD_US as f64) as i64),
+            Some(CPU_PERIOD_US),
+        ),
+        None => (None, None),
+    };
+
+    Resources {
+        memory: MemoryResources {
+            memory_hard_limit: config.memory_limit.map(|memory| memory as i64),
+            memory_swap_limit,
+            ..Default::default()
+        },
+        pid: PidResources {
+            maximum_number_of_processes: config.pids_limit.map(|pids| MaxValue::Value(pids as i64)),
+        },
+        cpu: CpuResources {
+            quota,
+            period,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_resources() {
+        let args = ResourceArgs {
+            memory: Some(512 * 1024 * 1024),
+            memory_swap: Some(1024 * 1024 * 1024),
+            cpus: Some(1.5),
+            pids_limit: Some(100),
+        };
+
+        let config = merge_resources(&ResourceConfig::default(), &args).unwrap();
+        assert_eq!(config.memory_limit, Some(512 * 1024 * 1024));
+        assert_eq!(config.memory_swap_limit, Some(1024 * 1024 * 1024));
+        assert_eq!(config.cpu_limit, Some(1.5));
+        assert_eq!(config.pids_limit, Some(100));
+
+        // Limits not given are kept.
+        let args = ResourceArgs {
+            cpus: Some(2.0),
+            ..Default::default()
+        };
+        let updated = merge_resources(&config, &args).unwrap();
+        assert_eq!(updated.memory_limit, Some(512 * 1024 * 1024));
+        assert_eq!(updated.cpu_limit, Some(2.0));
+    }
+
+    #[test]
+    fn test_merge_resources_invalid() {
+        let base = ResourceConfig::default();
+
+        let args = ResourceArgs {
+            cpus: Some(0.0),
+            ..Default::default()
+        };
+        assert!(merge_resources(&base, &args).is_err());
+
+        let args = ResourceArgs {
+            memory_swap: Some(1024),
+            ..Default::default()
+        };
+        assert!(merge_resources(&base, &args).is_err());
+
+        let args = ResourceArgs {
+            memory: Some(2048),
+            memory_swap: Some(1024),
+            ..Default::default()
+        };
+        assert!(merge_resources(&base, &args).is_err());
+    }
+
+    #[test]
+    fn test_cgroup_resources() {
+        let config = ResourceConfig {
+            memory_limit: Some(1024),
+            memory_swap_limit: Some(4096),
+            cpu_limit: Some(0.5),
+            pids_limit: Some(10),
+            disk_limit: None,
+        };
+
+        let v1 = cgroup_resources(&config, false);
+        assert_eq!(v1.memory.memory_hard_limit, Some(1024));
+        assert_eq!(v1.memory.memory_swap_limit, Some(4096));
+        assert_eq!(v1.cpu.quota, Some(50_000));
+        assert_eq!(v1.cpu.period, Some(CPU_PERIOD_US));
+        assert_eq!(
+            v1.pid.maximum_number_of_processes,
+            Some(MaxValue::Value(10))
+        );
+
+        let v2 = cgroup_resources(&config, true);
+        assert_eq!(v2.memory.memory_swap_limit, Some(3072));
+    }
+}
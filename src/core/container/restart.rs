@@ -0,0 +1,207 @@
+use std::time::{Duration, Instant};
+
+use cgroups_rs::Cgroup;
+use log::{error, info};
+use nix::{
+    errno::Errno,
+    sys::{signal::kill, wait::WaitStatus},
+    unistd::Pid,
+};
+use tokio::sync::Mutex;
+
+use crate::core::metas::{ContainerStatus, RestartPolicy, CONTAINER_METAS};
+
+use super::{health::watch_health, init::do_run, start::start_prepare, stop::do_stop};
+
+/// Delay before the first restart, doubled on every restart in a row.
+const RESTART_DELAY_BASE: Duration = Duration::from_millis(100);
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(60);
+/// A container running this long is considered healthy again, resetting the delay.
+const RESTART_RESET_AFTER: Duration = Duration::from_secs(10);
+const ADOPTED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Containers asked to stop, which must not be restarted when they go down.
+static STOP_REQUESTS: Mutex<Vec<String>> = Mutex::const_new(Vec::new());
+
+pub async fn request_stop(id: &str) {
+    let mut requests = STOP_REQUESTS.lock().await;
+    if !requests.iter().any(|request| request == id) {
+        requests.push(id.to_string());
+    }
+}
+
+pub async fn clear_stop_request(id: &str) {
+    STOP_REQUESTS.lock().await.retain(|request| request != id);
+}
+
+async fn take_stop_request(id: &str) -> bool {
+    let mut requests = STOP_REQUESTS.lock().await;
+    let len = requests.len();
+    requests.retain(|request| request != id);
+
+    requests.len() != len
+}
+
+/// Exit code of a container process, following the shell convention for signals.
+pub fn exit_code(status: WaitStatus) -> Option<i32> {
+    match status {
+        WaitStatus::Exited(_, code) => Some(code),
+        WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
+fn restart_delay(attempt: u32) -> Duration {
+    RESTART_DELAY_BASE
+        .saturating_mul(1 << attempt.min(16))
+        .min(RESTART_DELAY_MAX)
+}
+
+/// Restart the exited container `id` for as long as its restart policy asks to, `exit_code` is
+/// how it went down last time.
+pub async fn supervise(id: String, mut exit_code: Option<i32>) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+    let mut attempt = 0;
+
+    loop {
+        let Some(mut meta) = container_metas.get_meta_by_id(&id).await else {
+            return;
+        };
+
+        if take_stop_request(&id).await
+            || !meta
+                .restart_policy
+                .should_restart(exit_code, meta.state.restart_count)
+        {
+            return;
+        }
+
+        meta.state.status = ContainerStatus::Restarting;
+        meta.state.restart_count += 1;
+        if let Err(e) = container_metas
+            .update_state(id.clone(), meta.state.clone())
+            .await
+        {
+            error!("Failed to update container {} state: {:?}", meta.name, e);
+            return;
+        }
+
+        let delay = restart_delay(attempt);
+        info!(
+            "[Daemon] Restarting container {} in {:?}, restart count {}",
+            meta.name, delay, meta.state.restart_count
+        );
+        tokio::time::sleep(delay).await;
+
+        // It may be stopped or removed while waiting.
+        let Some(meta) = container_metas.get_meta_by_id(&id).await else {
+            return;
+        };
+        if take_stop_request(&id).await || meta.state.status != ContainerStatus::Restarting {
+            return;
+        }
+
+        let started_at = Instant::now();
+        exit_code = match start_prepare(&meta).await {
+            Ok((pty, sock, child)) => {
+                watch_health(meta.id.clone(), child.as_raw());
+
+                match do_run(meta.name, meta.id, child, pty, sock, None, true).await {
+                    Some(status) => self::exit_code(status),
+                    None => return,
+                }
+            }
+            Err(e) => {
+                error!("Failed to restart container {}: {:?}", meta.name, e);
+                let _ = container_metas
+                    .updates(id.clone(), ContainerStatus::Exited)
+                    .await;
+
+                None
+            }
+        };
+
+        attempt = if started_at.elapsed() >= RESTART_RESET_AFTER {
+            0
+        } else {
+            attempt + 1
+        };
+    }
+}
+
+/// Apply restart policies to the containers recorded before the daemon started.
+pub async fn restore_containers() {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    for meta in container_metas.get_all_metas().await {
+        match meta.state.status {
+            // Stopped by hand, or not meant to be restarted, which `always` overrides.
+            ContainerStatus::Exited | ContainerStatus::Dead => {
+                if meta.restart_policy != RestartPolicy::Always {
+                    continue;
+                }
+            }
+            ContainerStatus::Removing => continue,
+            _ => {
+                let hier = cgroups_rs::hierarchies::auto();
+                let cg = Cgroup::load(hier, format!("{}-{}", meta.name, meta.id));
+                if let Some(pid) = meta
+                    .state
+                    .pid
+                    .filter(|pid| cg.procs().iter().any(|proc| proc.pid == *pid as u64))
+                {
+                    info!(
+                        "[Daemon] Container {} outlived the last daemon, watching it",
+                        meta.name
+                    );
+                    tokio::spawn(adopt(meta.name, meta.id, pid));
+                    continue;
+                }
+
+                // Went down while there was no daemon.
+                do_stop(meta.name.clone(), meta.id.clone(), None).await;
+            }
+        }
+
+        tokio::spawn(supervise(meta.id, None));
+    }
+}
+
+/// Follow the container `id` started by an earlier daemon until its process `pid` exits, then
+/// handle it as any other container going down.
+async fn adopt(name: String, id: String, pid: i32) {
+    watch_health(id.clone(), pid);
+
+    // Not a child of this daemon, so there is no SIGCHLD nor exit status to wait for.
+    while kill(Pid::from_raw(pid), None) != Err(Errno::ESRCH) {
+        tokio::time::sleep(ADOPTED_POLL_INTERVAL).await;
+    }
+
+    do_stop(name, id.clone(), None).await;
+    supervise(id, None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::signal::Signal;
+
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let pid = Pid::from_raw(1);
+        assert_eq!(exit_code(WaitStatus::Exited(pid, 3)), Some(3));
+        assert_eq!(
+            exit_code(WaitStatus::Signaled(pid, Signal::SIGKILL, false)),
+            Some(137)
+        );
+        assert_eq!(exit_code(WaitStatus::StillAlive), None);
+    }
+
+    #[test]
+    fn test_restart_delay() {
+        assert_eq!(restart_delay(0), RESTART_DELAY_BASE);
+        assert_eq!(restart_delay(3), RESTART_DELAY_BASE * 8);
+        assert_eq!(restart_delay(30), RESTART_DELAY_MAX);
+    }
+}
@@ -1,35 +1,66 @@
 use cgroups_rs::Cgroup;
-use log::error;
+use log::{error, info};
+use nix::sys::signal::Signal;
 use tokio::net::UnixStream;
 
 use super::image::delete_workspace;
 use crate::core::cmd::RMArgs;
-use crate::core::metas::CONTAINER_METAS;
-use crate::core::{Msg, ROOT_PATH};
+use crate::core::image::release_layers;
+use crate::core::metas::{ContainerStatus, CONTAINER_METAS};
+use crate::core::network::{release_container, unpublish_ports};
+use crate::core::volume::release_volumes;
+use crate::core::ROOT_PATH;
 
+use super::bulk::{reply_selection_error, run_bulk, select_containers};
+use super::stop::{stop_one, KILL_TIMEOUT};
+
+/// Remove stopped containers, or running ones too if forced.
 pub async fn remove_container(rm_args: RMArgs, mut stream: UnixStream) {
-    let meta = match CONTAINER_METAS
-        .get()
-        .unwrap()
-        .get_meta_by_name(&rm_args.name)
-        .await
-    {
-        Some(meta) => meta,
-        None => {
-            error!(
-                "Failed to rm container {}, record does not exist",
-                &rm_args.name
-            );
+    let names = match select_containers(&rm_args.names, &rm_args.filters).await {
+        Ok(names) => names,
+        Err(e) => {
+            reply_selection_error("rm", e, &mut stream).await;
             return;
         }
     };
 
-    if meta.state.status.is_running() {
-        error!(
-            "Failed to rm container {}, it's still running",
-            &rm_args.name
-        );
-        return;
+    run_bulk(
+        names,
+        |name| remove_one(name, rm_args.force, rm_args.volumes),
+        &mut stream,
+    )
+    .await;
+}
+
+/// Remove container `name`, killing it first if `force`, and its anonymous volumes if `volumes`.
+/// The record is kept until the workspace and cgroup are gone, so a failed removal can be retried.
+pub(super) async fn remove_one(name: String, force: bool, volumes: bool) -> anyhow::Result<String> {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let mut meta = container_metas
+        .get_meta_by_name(&name)
+        .await
+        .ok_or(anyhow::anyhow!(
+            "Failed to rm container {name}, record does not exist"
+        ))?;
+
+    let active = meta.state.status.can_stop() || meta.state.status == ContainerStatus::Restarting;
+    if active && !force {
+        return Err(anyhow::anyhow!(
+            "Failed to rm container {name}, it's still running, stop it first or use --force"
+        ));
+    }
+    if active {
+        stop_one(name.clone(), Signal::SIGKILL as i32, KILL_TIMEOUT)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to rm container {name}: {e}"))?;
+
+        meta = container_metas
+            .get_meta_by_id(&meta.id)
+            .await
+            .ok_or(anyhow::anyhow!(
+                "Failed to rm container {name}, record does not exist"
+            ))?;
     }
 
     // Do some clean up.
@@ -37,32 +68,46 @@ pub async fn remove_container(rm_args: RMArgs, mut stream: UnixStream) {
     let root_path = format!("{}/{}", ROOT_PATH, name_id);
     let mnt_path = format!("{}/{}/mnt", ROOT_PATH, name_id);
 
+    delete_workspace(&root_path, &mnt_path, &meta.mounts)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!("Failed to rm container {name}, cannot clean up workspace: {e}")
+        })?;
+
     let hier = cgroups_rs::hierarchies::auto();
     let cg = Cgroup::load(hier, name_id);
+    cg.delete().map_err(|e| {
+        anyhow::anyhow!("Failed to rm container {name}, cannot clean up cgroup: {e}")
+    })?;
 
-    if let Err(e) = cg.delete() {
-        error!(
-            "Failed to rm container {}, cannot clean up cgroup: {}",
-            &rm_args.name, e
-        );
-    }
+    container_metas
+        .deregister(meta.id.clone())
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to rm container {name}, cannot deregister: {e}"))?;
+    info!("[Daemon] Container {} removed", name);
 
-    // TODO: volume support needed.
-    if let Err(e) = delete_workspace(&root_path, &mnt_path, &None).await {
-        error!(
-            "Failed to rm container {}, cannot clean up workspace: {}",
-            &rm_args.name, e
-        );
-    }
+    // The container is gone by now, what is left only leaks resources.
+    let mut errors = vec![];
+    if let Some(network) = &meta.network {
+        // Normally gone since the container stopped, but make sure no rule is left behind.
+        if let Err(e) = unpublish_ports(&meta.id, network).await {
+            errors.push(format!("cannot unpublish ports: {e}"));
+        }
 
-    if let Err(e) = CONTAINER_METAS.get().unwrap().deregister(meta.id).await {
-        error!(
-            "Failed to rm container {}, cannot deregister container: {}",
-            &rm_args.name, e
-        );
+        if let Err(e) = release_container(network).await {
+            errors.push(format!("cannot release network: {e}"));
+        }
     }
+    errors.extend(release_volumes(&meta.mounts, volumes).await);
+    errors.extend(release_layers(&meta.layers).await);
 
-    let _ = Msg::OkContent(format!("Container {} removed", &rm_args.name))
-        .send_to(&mut stream)
-        .await;
+    if errors.is_empty() {
+        Ok(format!("Container {name} removed"))
+    } else {
+        error!("Container {} removed, but {}", name, errors.join(", "));
+        Err(anyhow::anyhow!(
+            "Container {name} removed, but {}",
+            errors.join(", ")
+        ))
+    }
 }
@@ -1,6 +1,7 @@
 use std::{
     io::{Read, Write},
     os::unix::net::UnixStream as StdUnixStream,
+    path::Path,
 };
 
 use cgroups_rs::{Cgroup, CgroupPid};
@@ -11,76 +12,122 @@ use nix::{
 };
 use tokio::net::UnixStream;
 
-use super::init::{do_run, new_container_process};
+use super::{
+    bulk::{reply_selection_error, run_bulk, select_containers},
+    health::watch_health,
+    image::{mount_volumes, umount_volumes},
+    init::{do_run, new_container_process},
+    process::ProcessConfig,
+    resource::apply_resources,
+    restart::{clear_stop_request, exit_code, supervise},
+};
 use crate::core::{
     cmd::StartArgs,
     metas::{ContainerMeta, ContainerStatus, CONTAINER_METAS},
+    network::{connect_container, publish_ports},
 };
 use crate::core::{Msg, ROOT_PATH};
 
+/// Start stopped containers, a single one may be attached to the client.
 pub async fn start_container(start_args: StartArgs, mut stream: UnixStream) {
-    let meta = match CONTAINER_METAS
-        .get()
-        .unwrap()
-        .get_meta_by_name(&start_args.name)
-        .await
-    {
-        Some(meta) => meta,
-        None => {
-            error!(
-                "Failed to start container {}, record does not exist",
-                &start_args.name
-            );
-            let _ = Msg::Err(format!(
-                "Failed to start container {}, record does not exist",
-                &start_args.name
-            ))
-            .send_to(&mut stream)
-            .await;
+    if let ([name], []) = (start_args.names.as_slice(), start_args.filters.as_slice()) {
+        let (meta, pty, sock, child) = match start_one(name).await {
+            Ok(res) => res,
+            Err(e) => {
+                error!("{:?}", e);
+                let _ = Msg::Err(e.to_string()).send_to(&mut stream).await;
 
-            return;
-        }
-    };
+                return;
+            }
+        };
 
-    if meta.state.status.is_running() {
-        error!(
-            "Failed to start container {}, it's already running",
-            &start_args.name
-        );
-        let _ = Msg::Err(format!(
-            "Failed to start container {}, it's already running",
-            &start_args.name
-        ))
-        .send_to(&mut stream)
-        .await;
+        let stream = (!start_args.detach).then_some(stream);
+        run_started(meta, pty, sock, child, stream).await;
 
         return;
     }
 
-    let (pty, sock, child) = match start_prepare(&meta).await {
-        Ok(res) => res,
+    let names = match select_containers(&start_args.names, &start_args.filters).await {
+        Ok(names) => names,
         Err(e) => {
-            error!("Failed to start container: {:?}", e);
-            let _ = Msg::Err(e.to_string()).send_to(&mut stream).await;
-
+            reply_selection_error("start", e, &mut stream).await;
             return;
         }
     };
 
-    do_run(
-        meta.name,
-        meta.id,
-        child,
-        pty,
-        sock,
-        stream,
-        start_args.detach,
-        true,
+    run_bulk(
+        names,
+        |name| async move {
+            let (meta, pty, sock, child) = start_one(&name).await?;
+            tokio::spawn(run_started(meta, pty, sock, child, None));
+
+            Ok(format!("Container {name} started"))
+        },
+        &mut stream,
     )
     .await;
 }
 
-async fn start_prepare(
+/// Start the process of a stopped container.
+async fn start_one(
+    name: &str,
+) -> anyhow::Result<(ContainerMeta, OpenptyResult, StdUnixStream, Pid)> {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let mut meta = container_metas
+        .get_meta_by_name(name)
+        .await
+        .ok_or(anyhow::anyhow!(
+            "Failed to start container {name}, record does not exist"
+        ))?;
+
+    if meta.state.status.can_stop() {
+        return Err(anyhow::anyhow!(
+            "Failed to start container {name}, it's already running"
+        ));
+    }
+
+    if meta.state.status == ContainerStatus::Restarting {
+        return Err(anyhow::anyhow!(
+            "Failed to start container {name}, it's restarting"
+        ));
+    }
+
+    // A manual start begins a new round of restarts.
+    clear_stop_request(&meta.id).await;
+    if meta.state.restart_count != 0 {
+        meta.state.restart_count = 0;
+        if let Err(e) = container_metas
+            .update_state(meta.id.clone(), meta.state.clone())
+            .await
+        {
+            error!("Failed to reset restart count: {:?}", e);
+        }
+    }
+
+    let (pty, sock, child) = start_prepare(&meta)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to start container {name}: {e}"))?;
+
+    Ok((meta, pty, sock, child))
+}
+
+/// Drive a started container until it exits, and restart it as its policy asks to.
+async fn run_started(
+    meta: ContainerMeta,
+    pty: OpenptyResult,
+    sock: StdUnixStream,
+    child: Pid,
+    stream: Option<UnixStream>,
+) {
+    watch_health(meta.id.clone(), child.as_raw());
+
+    if let Some(status) = do_run(meta.name, meta.id.clone(), child, pty, sock, stream, true).await {
+        supervise(meta.id, exit_code(status)).await;
+    }
+}
+
+pub(super) async fn start_prepare(
     meta: &ContainerMeta,
 ) -> anyhow::Result<(OpenptyResult, StdUnixStream, Pid)> {
     let name_id = format!("{}-{}", &meta.name, &meta.id);
@@ -92,8 +139,21 @@ async fn start_prepare(
     let (mut p_sock, c_sock) = StdUnixStream::pair()?;
     let mut buf = [0u8; 4];
 
+    // Volumes are unmounted when the container stops, but may be left mounted if the daemon went
+    // down with it.
+    umount_volumes(Path::new(&mnt_path), &meta.mounts)?;
+    mount_volumes(Path::new(&mnt_path), &meta.mounts).await?;
+
+    let process = ProcessConfig::resolve(
+        Path::new(&mnt_path),
+        &meta.env,
+        meta.working_dir.as_deref(),
+        meta.user.as_deref(),
+    )?;
+
     // Create a new process with old namespaces.
-    let child = match new_container_process(&mnt_path, c_sock, &pty, &meta.command) {
+    let child = match new_container_process(&mnt_path, c_sock, &pty, &meta.command_line(), &process)
+    {
         Ok(child) => child,
         Err(e) => {
             return Err(e);
@@ -116,17 +176,88 @@ async fn start_prepare(
     let hier = cgroups_rs::hierarchies::auto();
     let cg = Cgroup::load(hier, name_id);
 
+    // The cgroup does not survive a reboot, create it again in that case.
+    if !cg.exists() {
+        if let Err(e) = cg.create() {
+            let _ = p_sock.write(b"EXIT");
+
+            return Err(anyhow::anyhow!("Failed to create cgroup: {:?}", e));
+        }
+    }
+
+    // Limits may have been lost or updated while stopped, so write them again.
+    if let Err(e) = apply_resources(&cg, &meta.resources) {
+        let _ = p_sock.write(b"EXIT");
+
+        return Err(e);
+    }
+
     if let Err(e) = cg.add_task_by_tgid(CgroupPid::from(child.as_raw() as u64)) {
         p_sock.write(b"EXIT").unwrap();
 
         return Err(anyhow::anyhow!("Failed to add task to cgroup: {:?}", e));
     }
 
+    // Reconnect the new net namespace, keeping the address the container owns.
+    if let Some(network) = &meta.network {
+        // An address that does not parse is still allocated to the container, taking another one
+        // would leak it.
+        let ip = match network.ip_address.as_deref().map(str::parse).transpose() {
+            Ok(ip) => ip,
+            Err(e) => {
+                let _ = p_sock.write(b"EXIT");
+                let _ = cg.kill();
+
+                return Err(anyhow::anyhow!(
+                    "Invalid address {:?} of network {}: {}",
+                    network.ip_address,
+                    network.network_name,
+                    e
+                ));
+            }
+        };
+
+        let mut new_network =
+            match connect_container(&network.network_name, &meta.id, child.as_raw(), ip).await {
+                Ok(new_network) => new_network,
+                Err(e) => {
+                    let _ = p_sock.write(b"EXIT");
+                    let _ = cg.kill();
+
+                    return Err(anyhow::anyhow!(
+                        "Failed to connect network {}: {:?}",
+                        network.network_name,
+                        e
+                    ));
+                }
+            };
+        new_network.ports = network.ports.clone();
+
+        // Another container may have taken the published ports while this one was stopped.
+        if let Err(e) = publish_ports(&meta.id, &mut new_network, &[]).await {
+            let _ = p_sock.write(b"EXIT");
+            let _ = cg.kill();
+
+            return Err(anyhow::anyhow!("Failed to publish ports: {:?}", e));
+        }
+
+        if let Err(e) = CONTAINER_METAS
+            .get()
+            .unwrap()
+            .attach_network(meta.id.clone(), new_network)
+            .await
+        {
+            error!("Failed to update container network: {:?}", e);
+        }
+    }
+
     // Updates records.
+    let mut running = meta.clone();
+    running.set_running(child.as_raw());
     if let Err(e) = CONTAINER_METAS
         .get()
         .unwrap()
-        .updates(meta.id.clone(), ContainerStatus::Running)
+        .update_state(meta.id.clone(), running.state)
         .await
     {
         error!("Failed to update container status: {:?}", e);
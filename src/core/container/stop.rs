@@ -1,69 +1,190 @@
+use std::{path::Path, time::Duration};
+
 use cgroups_rs::Cgroup;
-use log::{error, info};
-use tokio::net::UnixStream;
+use log::{error, info, warn};
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{kill, Signal},
+        wait::WaitStatus,
+    },
+    unistd::Pid,
+};
+use tokio::{
+    net::UnixStream,
+    signal::unix::{signal, SignalKind},
+};
 
-use crate::core::{
-    cmd::StopArgs,
-    metas::{ContainerStatus, CONTAINER_METAS},
-    Msg,
+use super::{
+    bulk::{reply_selection_error, run_bulk, select_containers},
+    image::umount_volumes,
+    pause::set_frozen,
+    restart::{exit_code, request_stop},
 };
 
-/// Stop a running container.
+use crate::core::{cmd::StopArgs, metas::CONTAINER_METAS, network::unpublish_ports, ROOT_PATH};
+
+/// How long to wait for a container to exit once killed.
+pub(super) const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stop running containers.
 pub async fn stop_container(stop_args: StopArgs, mut stream: UnixStream) {
-    // Let's first get the container pid.
-    let container_metas = match CONTAINER_METAS.get() {
-        Some(metas) => metas,
-        None => {
-            error!("Container metas not initialized");
-            let _ = Msg::Err("Container metas not initialized".to_string())
-                .send_to(&mut stream)
-                .await;
+    let names = match select_containers(&stop_args.names, &stop_args.filters).await {
+        Ok(names) => names,
+        Err(e) => {
+            reply_selection_error("stop", e, &mut stream).await;
             return;
         }
     };
 
-    let meta = match container_metas.get_meta_by_name(&stop_args.name).await {
-        Some(meta) => meta,
-        None => {
-            error!(
-                "Failed to stop container {}, record does not exist",
-                &stop_args.name
-            );
-
-            let _ = Msg::Err(format!(
-                "Failed to stop container {}, record does not exist",
-                &stop_args.name
-            ))
-            .send_to(&mut stream)
-            .await;
+    let timeout = Duration::from_secs(stop_args.time);
+    run_bulk(
+        names,
+        |name| stop_one(name, stop_args.signal, timeout),
+        &mut stream,
+    )
+    .await;
+}
+
+pub(super) async fn stop_one(
+    name: String,
+    signal: i32,
+    timeout: Duration,
+) -> anyhow::Result<String> {
+    let meta = CONTAINER_METAS
+        .get()
+        .unwrap()
+        .get_meta_by_name(&name)
+        .await
+        .ok_or(anyhow::anyhow!(
+            "Failed to stop container {name}, record does not exist"
+        ))?;
 
-            return;
+    // The container is not to be restarted when it goes down.
+    request_stop(&meta.id).await;
+
+    match meta.state.pid {
+        Some(pid) if meta.state.status.can_stop() => {
+            graceful_stop(&meta.name, &meta.id, pid, signal, timeout)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to stop container {name}: {e}"))?;
         }
-    };
+        _ => do_stop(meta.name, meta.id, None).await,
+    }
+
+    Ok(format!("Container {name} stopped"))
+}
+
+/// Send `signal` to the init process `pid` of a container and wait for it to exit, killing the
+/// whole container if it is still up after `timeout`.
+async fn graceful_stop(
+    name: &str,
+    id: &str,
+    pid: i32,
+    signal: i32,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    // A frozen process does not handle the signal.
+    if let Err(e) = set_frozen(&format!("{name}-{id}"), false) {
+        warn!("Failed to thaw container {}: {:?}", name, e);
+    }
+
+    let signal = Signal::try_from(signal)?;
+    match kill(Pid::from_raw(pid), signal) {
+        Ok(()) | Err(Errno::ESRCH) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    if tokio::time::timeout(timeout, wait_exit(id, pid))
+        .await
+        .is_err()
+    {
+        warn!(
+            "[Daemon] Container {} did not exit in {:?} after {}, killing it",
+            name, timeout, signal
+        );
+
+        let hier = cgroups_rs::hierarchies::auto();
+        let cg = Cgroup::load(hier, format!("{name}-{id}"));
+        cg.kill()
+            .map_err(|e| anyhow::anyhow!("Failed to kill container: {}", e))?;
+
+        tokio::time::timeout(KILL_TIMEOUT, wait_exit(id, pid))
+            .await
+            .map_err(|_| anyhow::anyhow!("Container did not exit after being killed"))??;
+    }
+
+    Ok(())
+}
+
+/// Wait for the init process `pid` of container `id` to exit, that is until its waiter has
+/// recorded the exit. Containers started by an earlier daemon are not children of this one, their
+/// waiter is the one `restore_containers` adopts them with, so they are stopped the same way.
+async fn wait_exit(id: &str, pid: i32) -> anyhow::Result<()> {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+    let mut sigchild = signal(SignalKind::child())?;
 
-    do_stop(meta.name, meta.id).await;
+    loop {
+        if container_metas
+            .get_meta_by_id(id)
+            .await
+            .is_none_or(|meta| meta.state.pid != Some(pid))
+        {
+            return Ok(());
+        }
 
-    let _ = Msg::OkContent(format!("Container {} stoped", &stop_args.name))
-        .send_to(&mut stream)
-        .await;
+        // The record is updated shortly after the child is reaped, so poll as well.
+        tokio::select! {
+            _ = sigchild.recv() => {}
+            _ = tokio::time::sleep(EXIT_POLL_INTERVAL) => {}
+        }
+    }
 }
 
-pub async fn do_stop(name: String, id: String) {
+/// Clean up a container whose init process is gone and record how it exited, if known.
+pub async fn do_stop(name: String, id: String, exit: Option<WaitStatus>) {
     let name_id = format!("{name}-{id}");
 
     // Get current cgroups
     let hier = cgroups_rs::hierarchies::auto();
-    let cg = Cgroup::load(hier, name_id);
+    let cg = Cgroup::load(hier, &name_id);
 
-    // Cgroup kills
+    // Cgroup kills, what is left of the container is cleaned up either way, as its init process
+    // is gone.
     if let Err(e) = cg.kill() {
-        error!("Failed to stop container {}: {}", name, e);
-        return;
+        error!("Failed to kill processes of container {}: {}", name, e);
+    }
+
+    // Processes of a paused container only die once thawed.
+    if let Err(e) = set_frozen(&name_id, false) {
+        error!("Failed to thaw container {}: {}", name, e);
     }
 
     // Update records.
     if let Some(container_metas) = CONTAINER_METAS.get() {
-        let _ = container_metas.updates(id, ContainerStatus::Exited).await;
+        if let Some(mut meta) = container_metas.get_meta_by_id(&id).await {
+            // Published ports point to an address nobody listens on now.
+            if let Some(network) = &meta.network {
+                if let Err(e) = unpublish_ports(&id, network).await {
+                    error!("Failed to unpublish ports of container {}: {}", name, e);
+                }
+            }
+
+            // Mounted again on start, so that a tmpfs starts empty.
+            let mnt_path = format!("{ROOT_PATH}/{name_id}/mnt");
+            if let Err(e) = umount_volumes(Path::new(&mnt_path), &meta.mounts) {
+                error!("Failed to unmount volumes of container {}: {}", name, e);
+            }
+
+            meta.set_stopped(exit.and_then(exit_code), None);
+            if let Some(WaitStatus::Signaled(_, signal, _)) = exit {
+                meta.state.signal = Some(signal.to_string());
+            }
+            if let Err(e) = container_metas.update_state(id, meta.state).await {
+                error!("Failed to update container {} state: {:?}", name, e);
+            }
+        }
     } else {
         error!("Container metas not initialized during stop");
     }
@@ -0,0 +1,84 @@
+use cgroups_rs::Cgroup;
+use log::{error, info};
+use tokio::net::UnixStream;
+
+use super::resource::{apply_resources, merge_resources};
+use crate::core::{cmd::UpdateArgs, metas::CONTAINER_METAS, Msg};
+
+/// Update resource limits of a container, running ones are updated in place.
+pub async fn update_container(update_args: UpdateArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&update_args.name).await {
+        Some(meta) => meta,
+        None => {
+            error!(
+                "Failed to update container {}, record does not exist",
+                &update_args.name
+            );
+            let _ = Msg::Err(format!(
+                "Failed to update container {}, record does not exist",
+                &update_args.name
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if update_args.resources.is_empty() {
+        let _ = Msg::Err("No resource limits given".to_string())
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    let resources = match merge_resources(&meta.resources, &update_args.resources) {
+        Ok(resources) => resources,
+        Err(e) => {
+            error!("Failed to update container {}: {}", &update_args.name, e);
+            let _ = Msg::Err(e.to_string()).send_to(&mut stream).await;
+
+            return;
+        }
+    };
+
+    // Stopped containers get the new limits on next start.
+    if meta.state.status.can_stop() {
+        let hier = cgroups_rs::hierarchies::auto();
+        let cg = Cgroup::load(hier, format!("{}-{}", meta.name, meta.id));
+
+        if let Err(e) = apply_resources(&cg, &resources) {
+            error!("Failed to update container {}: {}", &update_args.name, e);
+            let _ = Msg::Err(e.to_string()).send_to(&mut stream).await;
+
+            return;
+        }
+    }
+
+    if let Err(e) = container_metas
+        .update_container_resources(meta.id, resources)
+        .await
+    {
+        error!(
+            "Failed to update container {}, cannot update record: {}",
+            &update_args.name, e
+        );
+        let _ = Msg::Err(format!(
+            "Failed to update container {}, cannot update record: {}",
+            &update_args.name, e
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    info!("[Daemon] Container {} resources updated", &update_args.name);
+
+    let _ = Msg::OkContent(format!("Container {} updated", &update_args.name))
+        .send_to(&mut stream)
+        .await;
+}
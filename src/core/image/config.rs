@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// What an OCI image config sets for the containers run from the image, each a default the run
+/// arguments can override.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct RunConfig {
+    pub user: Option<String>,
+    /// Ports as `<port>/<protocol>`, the values are always empty objects.
+    pub exposed_ports: Option<HashMap<String, Value>>,
+    /// Variables as `KEY=VALUE`.
+    pub env: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub working_dir: Option<String>,
+}
+
+/// Change to the run config of a committed image, as the Dockerfile instruction it comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ConfigChange {
+    Cmd(Vec<String>),
+    Entrypoint(Vec<String>),
+    Env(String, String),
+    /// Port as `<port>/<protocol>`.
+    Expose(String),
+    User(String),
+    Workdir(String),
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    config: Option<RunConfig>,
+}
+
+impl RunConfig {
+    /// Parse the run config out of an OCI image config, images without one run with none.
+    pub fn parse(config: Option<&str>) -> anyhow::Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+
+        let file: ConfigFile = serde_json::from_str(config)
+            .map_err(|e| anyhow::anyhow!("Invalid image config: {e}"))?;
+
+        Ok(file.config.unwrap_or_default())
+    }
+
+    /// The environment of the image, to be overridden by that of the run.
+    pub fn env(&self) -> HashMap<String, String> {
+        self.env
+            .iter()
+            .flatten()
+            .map(|var| match var.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (var.clone(), String::new()),
+            })
+            .collect()
+    }
+
+    /// TCP ports the image exposes.
+    pub fn exposed_tcp_ports(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = self
+            .exposed_ports
+            .iter()
+            .flatten()
+            .filter_map(|(port, _)| match port.split_once('/') {
+                Some((port, "tcp")) => port.parse().ok(),
+                Some(_) => None,
+                None => port.parse().ok(),
+            })
+            .filter(|port| *port != 0)
+            .collect();
+        ports.sort_unstable();
+
+        ports
+    }
+
+    /// Working directory of the image, if set.
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_deref().filter(|dir| !dir.is_empty())
+    }
+
+    /// User of the image, if set.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref().filter(|user| !user.is_empty())
+    }
+
+    /// Split what a container runs into the command and its args. `entrypoint` replaces that of
+    /// the image and drops its cmd as well, an empty one runs the command as is. `command`, if
+    /// any, replaces the cmd. The command is the entrypoint, or the program without one.
+    pub fn command(
+        &self,
+        entrypoint: Option<&str>,
+        command: &[String],
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let (entrypoint, cmd) = match entrypoint {
+            Some("") => (vec![], vec![]),
+            Some(entrypoint) => (vec![entrypoint.to_string()], vec![]),
+            None => (
+                self.entrypoint.clone().unwrap_or_default(),
+                self.cmd.clone().unwrap_or_default(),
+            ),
+        };
+        let args = if command.is_empty() {
+            cmd
+        } else {
+            command.to_vec()
+        };
+
+        match (entrypoint.is_empty(), args.split_first()) {
+            (false, _) => Ok((entrypoint, args)),
+            (true, Some((program, args))) => Ok((vec![program.clone()], args.to_vec())),
+            (true, None) => Err(anyhow::anyhow!(
+                "No command specified, and the image has none"
+            )),
+        }
+    }
+}
+
+impl ConfigChange {
+    /// Apply the change to `config`, the run config object of an OCI image config.
+    pub fn apply(&self, config: &mut Map<String, Value>) {
+        match self {
+            Self::Cmd(cmd) => {
+                config.insert("Cmd".to_string(), json!(cmd));
+            }
+            Self::Entrypoint(entrypoint) => {
+                config.insert("Entrypoint".to_string(), json!(entrypoint));
+            }
+            Self::Env(key, value) => {
+                let env = config.entry("Env").or_insert(json!([]));
+                if !env.is_array() {
+                    *env = json!([]);
+                }
+                let vars = env.as_array_mut().unwrap();
+                vars.retain(|var| {
+                    var.as_str()
+                        .is_none_or(|var| var.split('=').next() != Some(key.as_str()))
+                });
+                vars.push(json!(format!("{key}={value}")));
+            }
+            Self::Expose(port) => {
+                let ports = config.entry("ExposedPorts").or_insert(json!({}));
+                if !ports.is_object() {
+                    *ports = json!({});
+                }
+                ports
+                    .as_object_mut()
+                    .unwrap()
+                    .insert(port.clone(), json!({}));
+            }
+            Self::User(user) => {
+                config.insert("User".to_string(), json!(user));
+            }
+            Self::Workdir(dir) => {
+                config.insert("WorkingDir".to_string(), json!(dir));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_run_config() {
+        let config = RunConfig::parse(Some(
+            r#"{
+                "architecture": "amd64",
+                "config": {
+                    "User": "",
+                    "ExposedPorts": {"80/tcp": {}, "53/udp": {}, "8080": {}},
+                    "Env": ["PATH=/usr/bin:/bin", "EMPTY"],
+                    "Entrypoint": null,
+                    "Cmd": ["nginx", "-g", "daemon off;"],
+                    "WorkingDir": "/srv"
+                },
+                "rootfs": {"type": "layers", "diff_ids": []}
+            }"#,
+        ))
+        .unwrap();
+
+        assert_eq!(config.user(), None);
+        assert_eq!(config.working_dir(), Some("/srv"));
+        assert_eq!(config.exposed_tcp_ports(), vec![80, 8080]);
+        assert_eq!(config.env()["PATH"], "/usr/bin:/bin");
+        assert_eq!(config.env()["EMPTY"], "");
+        assert_eq!(config.entrypoint, None);
+
+        assert_eq!(RunConfig::parse(None).unwrap(), RunConfig::default());
+        assert_eq!(
+            RunConfig::parse(Some(r#"{"rootfs": {}}"#)).unwrap(),
+            RunConfig::default()
+        );
+        assert!(RunConfig::parse(Some("not json")).is_err());
+    }
+
+    #[test]
+    fn test_command() {
+        let config = RunConfig {
+            entrypoint: Some(strings(&["/entrypoint.sh"])),
+            cmd: Some(strings(&["nginx", "-g", "daemon off;"])),
+            ..Default::default()
+        };
+
+        // Defaults of the image.
+        assert_eq!(
+            config.command(None, &[]).unwrap(),
+            (
+                strings(&["/entrypoint.sh"]),
+                strings(&["nginx", "-g", "daemon off;"])
+            )
+        );
+        // Args of the run replace the cmd.
+        assert_eq!(
+            config.command(None, &strings(&["sh"])).unwrap(),
+            (strings(&["/entrypoint.sh"]), strings(&["sh"]))
+        );
+        // An entrypoint replaces both.
+        assert_eq!(
+            config.command(Some("/bin/ls"), &[]).unwrap(),
+            (strings(&["/bin/ls"]), vec![])
+        );
+        assert_eq!(
+            config.command(Some(""), &strings(&["ls", "-l"])).unwrap(),
+            (strings(&["ls"]), strings(&["-l"]))
+        );
+        assert!(config.command(Some(""), &[]).is_err());
+        assert!(RunConfig::default().command(None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut config = json!({
+            "Env": ["PATH=/bin", "LANG=C"],
+            "Cmd": ["sh"],
+            "ExposedPorts": null,
+        });
+        let changes = [
+            ConfigChange::Cmd(strings(&["nginx", "-g", "daemon off;"])),
+            ConfigChange::Env("LANG".to_string(), "C.UTF-8".to_string()),
+            ConfigChange::Expose("80/tcp".to_string()),
+            ConfigChange::Workdir("/srv".to_string()),
+        ];
+        for change in &changes {
+            change.apply(config.as_object_mut().unwrap());
+        }
+
+        let config: RunConfig = serde_json::from_value(config).unwrap();
+        assert_eq!(config.cmd, Some(strings(&["nginx", "-g", "daemon off;"])));
+        assert_eq!(config.env, Some(strings(&["PATH=/bin", "LANG=C.UTF-8"])));
+        assert_eq!(config.exposed_tcp_ports(), vec![80]);
+        assert_eq!(config.working_dir(), Some("/srv"));
+        assert_eq!(config.user(), None);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    ffi::{CString, OsString},
+    fs::File,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use nix::{
+    errno::Errno,
+    libc,
+    sys::stat::{makedev, mknod, Mode, SFlag},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::core::ROOT_PATH;
+
+/// Read-only layer unpacked from a tarball, shared by all images and containers using it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Layer {
+    /// SHA-256 of the tarball, in hex.
+    pub digest: String,
+    /// Size of the tarball.
+    pub size: u64,
+    /// Containers running on the layer, it is only removed once none is left and no image has it.
+    pub ref_count: u32,
+}
+
+/// Where layer `digest` is kept, with its tarball in `layer.tar` and its files in `diff`.
+pub fn layer_dir(digest: &str) -> PathBuf {
+    Path::new(ROOT_PATH).join("layers").join(digest)
+}
+
+/// Files of layer `digest`, the overlay lowerdir.
+pub fn layer_root(digest: &str) -> PathBuf {
+    layer_dir(digest).join("diff")
+}
+
+/// Tarball layer `digest` was unpacked from.
+pub fn layer_tarball(digest: &str) -> PathBuf {
+    layer_dir(digest).join("layer.tar")
+}
+
+/// SHA-256 of the file at `path`, in hex.
+pub fn digest_file(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Unpack the tarball at `staged` as layer `digest`, and keep the tarball moved in with it. OCI
+/// whiteouts are turned into those of overlay, as layers are stacked as its lowerdirs.
+pub fn unpack_layer(staged: &Path, digest: &str) -> anyhow::Result<Layer> {
+    let dir = layer_dir(digest);
+    let root = layer_root(digest);
+    std::fs::create_dir_all(&root)?;
+
+    let unpacked = extract(staged, &root)
+        .and_then(|_| convert_whiteouts(&root))
+        .and_then(|_| Ok(std::fs::rename(staged, layer_tarball(digest))?))
+        .and_then(|_| Ok(std::fs::metadata(layer_tarball(digest))?.len()));
+    match unpacked {
+        Ok(size) => Ok(Layer {
+            digest: digest.to_string(),
+            size,
+            ref_count: 0,
+        }),
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            Err(e)
+        }
+    }
+}
+
+/// Unpack `tarball` into `root` with the owners, modes and xattrs it records. Owners are taken
+/// by number, as the names are those of the image, not of the host. Overlay xattrs are left out,
+/// a layer is not to make itself opaque or redirect to another.
+fn extract(tarball: &Path, root: &Path) -> anyhow::Result<()> {
+    let output = Command::new("tar")
+        .arg("--numeric-owner")
+        .arg("--same-owner")
+        .arg("--same-permissions")
+        .arg("--xattrs")
+        .arg("--xattrs-include=*")
+        .arg("--xattrs-exclude=trusted.overlay.*")
+        .arg("-xf")
+        .arg(tarball)
+        .arg("-C")
+        .arg(root)
+        .stdout(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to extract layer: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Turn the OCI whiteouts under `dir` into those of overlay: `.wh.<name>` hiding `<name>` of the
+/// layers below becomes a 0/0 character device, and `.wh..wh..opq` hiding everything below its
+/// directory makes the directory opaque.
+fn convert_whiteouts(dir: &Path) -> anyhow::Result<()> {
+    let entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name == ".wh..wh..opq" {
+            std::fs::remove_file(&path)?;
+            set_opaque(dir)?;
+        } else if let Some(hidden) = name.strip_prefix(".wh.") {
+            std::fs::remove_file(&path)?;
+            // What the layer itself adds under the same name is kept.
+            let hidden = dir.join(hidden);
+            if hidden.symlink_metadata().is_err() {
+                mknod(&hidden, SFlag::S_IFCHR, Mode::empty(), makedev(0, 0))?;
+            }
+        } else if entry.file_type()?.is_dir() {
+            convert_whiteouts(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pack the overlay upperdir `upper` into a layer tarball at `tarball`. Overlay whiteouts are
+/// turned into those of OCI in `copy`, a hard-linked copy of the upperdir, as it may be in use.
+pub fn export_layer(upper: &Path, copy: &Path, tarball: &Path) -> anyhow::Result<()> {
+    let exported = run_command(Command::new("cp").arg("-al").arg(upper).arg(copy))
+        .and_then(|_| to_oci_whiteouts(upper, copy))
+        .and_then(|_| {
+            run_command(
+                Command::new("tar")
+                    .arg("--numeric-owner")
+                    .arg("-cf")
+                    .arg(tarball)
+                    .arg("-C")
+                    .arg(copy)
+                    .arg("."),
+            )
+        });
+    let _ = std::fs::remove_dir_all(copy);
+
+    exported
+}
+
+fn run_command(command: &mut Command) -> anyhow::Result<()> {
+    let output = command.stdout(Stdio::null()).output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Turn the overlay whiteouts under `upper` into those of OCI under `copy`, the reverse of
+/// [`convert_whiteouts`].
+fn to_oci_whiteouts(upper: &Path, copy: &Path) -> anyhow::Result<()> {
+    if is_opaque(upper)? {
+        File::create(copy.join(".wh..wh..opq"))?;
+    }
+
+    for entry in std::fs::read_dir(upper)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let copied = copy.join(entry.file_name());
+
+        if file_type.is_char_device() && entry.metadata()?.rdev() == 0 {
+            std::fs::remove_file(&copied)?;
+            let mut whiteout = OsString::from(".wh.");
+            whiteout.push(entry.file_name());
+            File::create(copy.join(whiteout))?;
+        } else if file_type.is_dir() {
+            to_oci_whiteouts(&entry.path(), &copied)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_opaque(dir: &Path) -> anyhow::Result<bool> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    let mut value = [0u8; 1];
+
+    // SAFETY: The path and name are NUL terminated, and the value is as long as given.
+    let res = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            c"trusted.overlay.opaque".as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    match Errno::result(res) {
+        Ok(_) => Ok(&value == b"y"),
+        Err(Errno::ENODATA | Errno::ENOTSUP | Errno::ERANGE) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn set_opaque(dir: &Path) -> anyhow::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+
+    // SAFETY: The path and name are NUL terminated, and the value is as long as given.
+    let res = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            c"trusted.overlay.opaque".as_ptr(),
+            b"y".as_ptr().cast(),
+            1,
+            0,
+        )
+    };
+    Errno::result(res)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("layer.tar");
+        std::fs::write(&path, b"abc").unwrap();
+
+        assert_eq!(
+            digest_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,12 @@
+use tokio::sync::{Mutex, OnceCell};
+
+mod config;
+mod layer;
+mod oci;
+mod store;
+
+pub static IMAGES: OnceCell<Mutex<Images>> = OnceCell::const_new();
+pub use config::{ConfigChange, RunConfig};
+pub use layer::{export_layer, layer_root};
+pub use oci::{commit_config, load_image, save_image};
+pub use store::*;
@@ -0,0 +1,697 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use flate2::read::GzDecoder;
+use log::{error, info};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::net::UnixStream;
+
+use crate::core::{cmd::parse_image_ref, ImageLoadArgs, ImageSaveArgs, Msg};
+
+use super::{
+    config::ConfigChange,
+    layer::layer_tarball,
+    store::{staging_dir, staging_tarball, Image},
+    IMAGES,
+};
+
+const INDEX_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+const DOCKER_LIST_TYPE: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// Annotation of the full reference of an image, as set by containerd and Docker.
+const IMAGE_NAME: &str = "io.containerd.image.name";
+/// Annotation of the reference of an image, or only its tag.
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+    size: u64,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    annotations: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<Platform>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Index {
+    schema_version: u32,
+    #[serde(default)]
+    media_type: Option<String>,
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    schema_version: u32,
+    #[serde(default)]
+    media_type: Option<String>,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+/// Entry of the `manifest.json` of a `docker save` archive, with paths within the archive.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifest {
+    config: String,
+    repo_tags: Option<Vec<String>>,
+    layers: Vec<String>,
+}
+
+/// Image found in an archive, with the paths of its layers bottom first.
+#[derive(Debug)]
+struct ArchiveImage {
+    references: Vec<String>,
+    config: String,
+    layers: Vec<PathBuf>,
+}
+
+/// Load the images of an OCI layout or a `docker save` archive.
+pub async fn load_image(args: ImageLoadArgs, mut stream: UnixStream) {
+    match load(Path::new(&args.input)).await {
+        Ok(loaded) => {
+            info!("[Daemon] Images loaded from {}", args.input);
+            let _ = Msg::OkContent(loaded.join("\n")).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!("Failed to load images from {}: {:?}", args.input, e);
+            let _ = Msg::Err(format!("Failed to load images from {}: {}", args.input, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+async fn load(input: &Path) -> anyhow::Result<Vec<String>> {
+    if input.is_dir() {
+        return load_archive(input, false).await;
+    }
+
+    // A tarball is unpacked within the store, so that its layers can be linked in.
+    let root = staging_dir();
+    std::fs::create_dir_all(&root)?;
+    let loaded = match extract(input, &root) {
+        Ok(()) => load_archive(&root, true).await,
+        Err(e) => Err(e),
+    };
+    let _ = std::fs::remove_dir_all(&root);
+
+    loaded
+}
+
+/// Add the images of the archive unpacked at `root`. The layers are linked from an archive
+/// `owned` by the store, and copied from others.
+async fn load_archive(root: &Path, owned: bool) -> anyhow::Result<Vec<String>> {
+    let images = if root.join("index.json").exists() {
+        oci_images(root)?
+    } else if root.join("manifest.json").exists() {
+        docker_images(root)?
+    } else {
+        return Err(anyhow::anyhow!(
+            "Neither an OCI layout nor a docker save archive"
+        ));
+    };
+
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    let mut loaded = vec![];
+    for image in images {
+        // Images of an archive may share layers, each is given its own staged file.
+        let mut staged = vec![];
+        for layer in &image.layers {
+            let copy = staging_tarball();
+            let res = if owned {
+                std::fs::hard_link(layer, &copy)
+            } else {
+                std::fs::copy(layer, &copy).map(|_| ())
+            };
+            if let Err(e) = res {
+                let _ = std::fs::remove_file(&copy);
+                remove_staged(&staged);
+                return Err(anyhow::anyhow!("Cannot stage layer {:?}: {}", layer, e));
+            }
+            staged.push(copy);
+        }
+
+        let id = match images_locked.add(&[], &staged, Some(image.config), &image.references) {
+            Ok(id) => id,
+            Err(e) => {
+                remove_staged(&staged);
+                return Err(e);
+            }
+        };
+
+        if image.references.is_empty() {
+            loaded.push(format!("Loaded image ID: {id}"));
+        }
+        for reference in image.references {
+            loaded.push(format!("Loaded image: {reference}"));
+        }
+    }
+
+    Ok(loaded)
+}
+
+fn remove_staged(staged: &[PathBuf]) {
+    for path in staged {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Images of the OCI layout at `root`, taking the manifest of this platform from an index of
+/// several platforms.
+fn oci_images(root: &Path) -> anyhow::Result<Vec<ArchiveImage>> {
+    let index: Index = read_json(&root.join("index.json"))?;
+
+    let mut images = vec![];
+    for descriptor in index.manifests {
+        let references = annotated_reference(&descriptor.annotations)
+            .into_iter()
+            .collect();
+        let manifest = find_manifest(root, descriptor)?;
+
+        let config = std::fs::read_to_string(blob_path(root, &manifest.config.digest)?)?;
+        let layers = manifest
+            .layers
+            .iter()
+            .map(|layer| blob_path(root, &layer.digest))
+            .collect::<anyhow::Result<_>>()?;
+
+        images.push(ArchiveImage {
+            references,
+            config,
+            layers,
+        });
+    }
+
+    Ok(images)
+}
+
+fn find_manifest(root: &Path, descriptor: Descriptor) -> anyhow::Result<Manifest> {
+    let path = blob_path(root, &descriptor.digest)?;
+
+    if descriptor.media_type != INDEX_TYPE && descriptor.media_type != DOCKER_LIST_TYPE {
+        return read_json(&path);
+    }
+
+    let index: Index = read_json(&path)?;
+    let platform = host_platform();
+    let nested = index
+        .manifests
+        .iter()
+        .find(|manifest| manifest.platform.as_ref() == Some(&platform))
+        .or(index.manifests.first())
+        .ok_or(anyhow::anyhow!("Index {} is empty", descriptor.digest))?;
+
+    find_manifest(root, nested.clone())
+}
+
+/// Images of the `docker save` archive at `root`.
+fn docker_images(root: &Path) -> anyhow::Result<Vec<ArchiveImage>> {
+    let manifests: Vec<DockerManifest> = read_json(&root.join("manifest.json"))?;
+
+    manifests
+        .into_iter()
+        .map(|manifest| {
+            Ok(ArchiveImage {
+                references: manifest
+                    .repo_tags
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|tag| normalize_reference(tag))
+                    .collect(),
+                config: std::fs::read_to_string(archive_path(root, &manifest.config)?)?,
+                layers: manifest
+                    .layers
+                    .iter()
+                    .map(|layer| archive_path(root, layer))
+                    .collect::<anyhow::Result<_>>()?,
+            })
+        })
+        .collect()
+}
+
+/// Reference of an image from its annotations, a bare tag is not enough to name an image.
+fn annotated_reference(annotations: &HashMap<String, String>) -> Option<String> {
+    match annotations.get(IMAGE_NAME) {
+        Some(name) => normalize_reference(name),
+        None => annotations
+            .get(REF_NAME)
+            .filter(|name| name.contains([':', '/']))
+            .and_then(|name| normalize_reference(name)),
+    }
+}
+
+/// Reference as used in the store, dropping the default registry of Docker.
+fn normalize_reference(reference: &str) -> Option<String> {
+    let reference = reference.strip_prefix("docker.io/").unwrap_or(reference);
+    let reference = reference.strip_prefix("library/").unwrap_or(reference);
+
+    parse_image_ref(reference).ok()
+}
+
+/// Path of blob `digest` in the OCI layout at `root`.
+fn blob_path(root: &Path, digest: &str) -> anyhow::Result<PathBuf> {
+    let hex = digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or(anyhow::anyhow!("Invalid digest: {}", digest))?;
+
+    Ok(root.join("blobs").join("sha256").join(hex))
+}
+
+/// Path of a file in the archive at `root`, which must not lead out of it.
+fn archive_path(root: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let within = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !within {
+        return Err(anyhow::anyhow!("Invalid path in archive: {}", path));
+    }
+
+    Ok(root.join(path))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", path, e))?;
+
+    serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid {:?}: {}", path, e))
+}
+
+fn extract(tarball: &Path, root: &Path) -> anyhow::Result<()> {
+    let output = Command::new("tar")
+        .arg("-xf")
+        .arg(tarball)
+        .arg("-C")
+        .arg(root)
+        .stdout(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to extract archive: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+fn host_platform() -> Platform {
+    let architecture = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+
+    Platform {
+        architecture: architecture.to_string(),
+        os: "linux".to_string(),
+    }
+}
+
+/// Save images as an OCI layout.
+pub async fn save_image(args: ImageSaveArgs, mut stream: UnixStream) {
+    match save(&args.names, Path::new(&args.output)).await {
+        Ok(()) => {
+            info!("[Daemon] Images saved to {}", args.output);
+            let _ = Msg::OkContent(format!(
+                "Saved {} to {}",
+                args.names.join(", "),
+                args.output
+            ))
+            .send_to(&mut stream)
+            .await;
+        }
+        Err(e) => {
+            error!("Failed to save images to {}: {:?}", args.output, e);
+            let _ = Msg::Err(format!("Failed to save images to {}: {}", args.output, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+async fn save(names: &[String], output: &Path) -> anyhow::Result<()> {
+    // Only what is created here is removed on failure, never what the user had there.
+    if output.symlink_metadata().is_ok() {
+        return Err(anyhow::anyhow!("{} already exists", output.display()));
+    }
+
+    if output.extension().is_none_or(|ext| ext != "tar") {
+        std::fs::create_dir(output)?;
+        let saved = write_layout(names, output).await;
+        if saved.is_err() {
+            let _ = std::fs::remove_dir_all(output);
+        }
+        return saved;
+    }
+
+    File::create_new(output)?;
+    let layout = staging_dir();
+    let saved = match write_layout(names, &layout).await {
+        Ok(()) => archive(&layout, output),
+        Err(e) => Err(e),
+    };
+    if saved.is_err() {
+        let _ = std::fs::remove_file(output);
+    }
+    let _ = std::fs::remove_dir_all(&layout);
+
+    saved
+}
+
+/// Write `names` as an OCI layout at `layout`, with an entry in the index for each reference.
+async fn write_layout(names: &[String], layout: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(layout.join("blobs").join("sha256"))?;
+
+    let images_locked = IMAGES.get().unwrap().lock().await;
+
+    let mut manifests = vec![];
+    for name in names {
+        let image = images_locked.resolve(name)?;
+        let descriptor = write_image(image, layout)?;
+
+        // Saved by reference, only that one is kept.
+        let references: Vec<_> = match parse_image_ref(name) {
+            Ok(reference) if image.references.contains(&reference) => vec![reference],
+            _ => image.references.clone(),
+        };
+        if references.is_empty() {
+            manifests.push(descriptor.clone());
+        }
+        for reference in references {
+            let tag = reference.rsplit_once(':').map_or("latest", |(_, tag)| tag);
+
+            let mut descriptor = descriptor.clone();
+            descriptor.annotations = HashMap::from([
+                (IMAGE_NAME.to_string(), reference.clone()),
+                (REF_NAME.to_string(), tag.to_string()),
+            ]);
+            manifests.push(descriptor);
+        }
+    }
+
+    let index = Index {
+        schema_version: 2,
+        media_type: Some(INDEX_TYPE.to_string()),
+        manifests,
+    };
+    std::fs::write(layout.join("index.json"), serde_json::to_vec(&index)?)?;
+    std::fs::write(
+        layout.join("oci-layout"),
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+
+    Ok(())
+}
+
+/// Write the blobs of `image` into `layout`, returning the descriptor of its manifest.
+fn write_image(image: &Image, layout: &Path) -> anyhow::Result<Descriptor> {
+    let mut layers = vec![];
+    for digest in &image.layers {
+        let tarball = layer_tarball(digest);
+        let blob = blob_path(layout, &format!("sha256:{digest}"))?;
+        if !blob.exists() {
+            std::fs::copy(&tarball, &blob)?;
+        }
+
+        layers.push(Descriptor {
+            media_type: layer_media_type(&tarball)?.to_string(),
+            digest: format!("sha256:{digest}"),
+            size: std::fs::metadata(&tarball)?.len(),
+            annotations: HashMap::new(),
+            platform: None,
+        });
+    }
+
+    let config = match &image.config {
+        Some(config) => config.clone(),
+        None => default_config(&image.layers)?,
+    };
+    let manifest = Manifest {
+        schema_version: 2,
+        media_type: Some(MANIFEST_TYPE.to_string()),
+        config: write_blob(layout, CONFIG_TYPE, config.as_bytes())?,
+        layers,
+    };
+
+    write_blob(layout, MANIFEST_TYPE, &serde_json::to_vec(&manifest)?)
+}
+
+fn write_blob(layout: &Path, media_type: &str, content: &[u8]) -> anyhow::Result<Descriptor> {
+    let digest = format!("sha256:{:x}", Sha256::digest(content));
+    std::fs::write(blob_path(layout, &digest)?, content)?;
+
+    Ok(Descriptor {
+        media_type: media_type.to_string(),
+        digest,
+        size: content.len() as u64,
+        annotations: HashMap::new(),
+        platform: None,
+    })
+}
+
+/// Config of an image imported without one, listing only its layers.
+pub(super) fn default_config(layers: &[String]) -> anyhow::Result<String> {
+    let diff_ids = layers
+        .iter()
+        .map(|digest| diff_id(&layer_tarball(digest)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let platform = host_platform();
+
+    let config = serde_json::json!({
+        "architecture": platform.architecture,
+        "os": platform.os,
+        "config": {},
+        "rootfs": {
+            "type": "layers",
+            "diff_ids": diff_ids,
+        },
+    });
+
+    Ok(config.to_string())
+}
+
+/// Config of an image committed with the layer tarball at `staged` on top of `layers`, based on
+/// `parent`, the config of the image they come from, if any.
+pub fn commit_config(
+    parent: Option<&str>,
+    layers: &[String],
+    staged: &Path,
+    author: Option<&str>,
+    message: Option<&str>,
+    changes: &[ConfigChange],
+) -> anyhow::Result<String> {
+    let mut config: Value = match parent {
+        Some(parent) => serde_json::from_str(parent)?,
+        None => {
+            let platform = host_platform();
+            json!({ "architecture": platform.architecture, "os": platform.os })
+        }
+    };
+    let fields = config
+        .as_object_mut()
+        .ok_or(anyhow::anyhow!("Invalid image config"))?;
+
+    let mut diff_ids = layers
+        .iter()
+        .map(|digest| diff_id(&layer_tarball(digest)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    diff_ids.push(diff_id(staged)?);
+    fields.insert(
+        "rootfs".to_string(),
+        json!({ "type": "layers", "diff_ids": diff_ids }),
+    );
+
+    let run_config = fields.entry("config").or_insert(json!({}));
+    if !run_config.is_object() {
+        *run_config = json!({});
+    }
+    for change in changes {
+        change.apply(run_config.as_object_mut().unwrap());
+    }
+
+    let mut entry = json!({ "created_by": "rtain commit" });
+    if let Some(author) = author {
+        fields.insert("author".to_string(), json!(author));
+        entry["author"] = json!(author);
+    }
+    if let Some(message) = message {
+        entry["comment"] = json!(message);
+    }
+    let history = fields.entry("history").or_insert(json!([]));
+    if !history.is_array() {
+        *history = json!([]);
+    }
+    history.as_array_mut().unwrap().push(entry);
+
+    Ok(config.to_string())
+}
+
+/// Media type of a layer tarball, by the compression its first bytes tell.
+fn layer_media_type(tarball: &Path) -> anyhow::Result<String> {
+    let mut magic = [0u8; 4];
+    let len = File::open(tarball)?.read(&mut magic)?;
+
+    Ok(match &magic[..len] {
+        [0x1f, 0x8b, ..] => format!("{LAYER_TYPE}+gzip"),
+        [0x28, 0xb5, 0x2f, 0xfd] => format!("{LAYER_TYPE}+zstd"),
+        _ => LAYER_TYPE.to_string(),
+    })
+}
+
+/// SHA-256 of a layer tarball uncompressed, as the config lists layers.
+fn diff_id(tarball: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+
+    let file = File::open(tarball)?;
+    match layer_media_type(tarball)?.rsplit_once('+') {
+        None => std::io::copy(&mut &file, &mut hasher)?,
+        Some((_, "gzip")) => std::io::copy(&mut GzDecoder::new(file), &mut hasher)?,
+        Some((_, compression)) => {
+            return Err(anyhow::anyhow!("Unsupported compression: {}", compression))
+        }
+    };
+
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
+
+fn archive(layout: &Path, output: &Path) -> anyhow::Result<()> {
+    let output = Command::new("tar")
+        .arg("-cf")
+        .arg(output)
+        .arg("-C")
+        .arg(layout)
+        .arg(".")
+        .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to archive layout: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotated_reference() {
+        let annotations = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            annotated_reference(&annotations(&[
+                (IMAGE_NAME, "docker.io/library/busybox:1.36"),
+                (REF_NAME, "1.36"),
+            ])),
+            Some("busybox:1.36".to_string())
+        );
+        assert_eq!(
+            annotated_reference(&annotations(&[(REF_NAME, "app/web:v1")])),
+            Some("app/web:v1".to_string())
+        );
+        assert_eq!(annotated_reference(&annotations(&[(REF_NAME, "v1")])), None);
+        assert_eq!(annotated_reference(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_archive_paths() {
+        let root = Path::new("/layout");
+        let hex = "a".repeat(64);
+
+        assert_eq!(
+            blob_path(root, &format!("sha256:{hex}")).unwrap(),
+            root.join("blobs/sha256").join(&hex)
+        );
+        assert!(blob_path(root, &format!("sha512:{hex}")).is_err());
+        assert!(blob_path(root, "sha256:../../etc/passwd").is_err());
+
+        assert_eq!(
+            archive_path(root, "abc/layer.tar").unwrap(),
+            root.join("abc/layer.tar")
+        );
+        assert!(archive_path(root, "../layer.tar").is_err());
+        assert!(archive_path(root, "/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_docker_manifest() {
+        let manifests: Vec<DockerManifest> = serde_json::from_str(
+            r#"[{"Config":"abc.json","RepoTags":["busybox:latest"],"Layers":["def/layer.tar"]},
+                {"Config":"ghi.json","RepoTags":null,"Layers":[]}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifests[0].config, "abc.json");
+        assert_eq!(manifests[0].layers, vec!["def/layer.tar"]);
+        assert_eq!(manifests[1].repo_tags, None);
+    }
+
+    #[test]
+    fn test_commit_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let staged = dir.path().join("layer.tar");
+        std::fs::write(&staged, b"abc").unwrap();
+
+        let parent = r#"{"architecture":"amd64","os":"linux",
+            "config":{"Env":["PATH=/bin"],"Cmd":["sh"]},
+            "rootfs":{"type":"layers","diff_ids":[]},
+            "history":[{"created_by":"import"}]}"#;
+        let config = commit_config(
+            Some(parent),
+            &[],
+            &staged,
+            Some("alice"),
+            Some("add nginx"),
+            &[ConfigChange::Cmd(vec!["nginx".to_string()])],
+        )
+        .unwrap();
+        let config: Value = serde_json::from_str(&config).unwrap();
+
+        assert_eq!(
+            config["rootfs"]["diff_ids"],
+            json!(["sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"])
+        );
+        assert_eq!(config["config"]["Cmd"], json!(["nginx"]));
+        assert_eq!(config["config"]["Env"], json!(["PATH=/bin"]));
+        assert_eq!(config["author"], "alice");
+        assert_eq!(config["history"][1]["comment"], "add nginx");
+
+        let config = commit_config(None, &[], &staged, None, None, &[]).unwrap();
+        let config: Value = serde_json::from_str(&config).unwrap();
+        assert_eq!(config["config"], json!({}));
+        assert_eq!(config["history"][0]["created_by"], "rtain commit");
+    }
+}
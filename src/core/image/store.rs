@@ -0,0 +1,629 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tabwriter::TabWriter;
+use tokio::net::UnixStream;
+
+use crate::core::{
+    cmd::parse_image_ref,
+    container::{format_size, random_id},
+    metas::{current_time, CONTAINER_METAS},
+    ImportArgs, Msg, RmiArgs, TagArgs, ROOT_PATH,
+};
+
+use super::{
+    layer::{digest_file, layer_dir, unpack_layer, Layer},
+    oci::default_config,
+    IMAGES,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Image {
+    /// SHA-256 of the config in hex, so that the same image is given the same ID wherever it
+    /// comes from.
+    pub id: String,
+    /// References as `name:tag`, none once all of them moved to other images.
+    pub references: Vec<String>,
+    pub created_at: u64,
+    /// Size of the layer tarballs.
+    pub size: u64,
+    /// Digests of the layers of the rootfs, bottom first.
+    pub layers: Vec<String>,
+    /// OCI image config as JSON, none for images imported before one was made for them.
+    pub config: Option<String>,
+}
+
+/// Images by ID and the layers they are made of, by digest.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Images {
+    images: HashMap<String, Image>,
+    layers: HashMap<String, Layer>,
+
+    path: PathBuf,
+}
+
+impl Images {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            let mut file = std::fs::File::open(&path)?;
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+
+            let mut images: Images = bincode::deserialize(&contents)?;
+            images.path = path;
+
+            Ok(images)
+        } else {
+            if let Some(parent_dir) = path.parent() {
+                std::fs::create_dir_all(parent_dir)?;
+            }
+
+            Ok(Images {
+                images: HashMap::new(),
+                layers: HashMap::new(),
+                path,
+            })
+        }
+    }
+
+    /// Save to a temporary file renamed into place, so that the index is never left half written.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let contents = bincode::serialize(self)?;
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Image by reference, or by its ID or a unique prefix of it.
+    pub fn resolve(&self, name: &str) -> anyhow::Result<&Image> {
+        let by_reference = parse_image_ref(name).ok().and_then(|reference| {
+            self.images
+                .values()
+                .find(|image| image.references.contains(&reference))
+        });
+        if let Some(image) = by_reference {
+            return Ok(image);
+        }
+
+        let mut matches = self
+            .images
+            .values()
+            .filter(|image| !name.is_empty() && image.id.starts_with(name));
+        match (matches.next(), matches.next()) {
+            (Some(image), None) => Ok(image),
+            (Some(_), Some(_)) => Err(anyhow::anyhow!("Image ID {} is ambiguous", name)),
+            _ => Err(anyhow::anyhow!("Image {} not found", name)),
+        }
+    }
+
+    /// Apply `change` and save, the change is undone if it cannot be saved.
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> anyhow::Result<()> {
+        let backup = (self.images.clone(), self.layers.clone());
+
+        change(self);
+        if let Err(e) = self.save() {
+            (self.images, self.layers) = backup;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Add an image made of the layer tarballs at `staged`, bottom first, stacked on the known
+    /// layers `parent`, with `config`, or one listing only its layers, and `references`. Each
+    /// tarball is unpacked into a layer, unless a layer was already made from the same content,
+    /// and taken out of `staged` either way. An image with the same config is only given the
+    /// references.
+    pub fn add(
+        &mut self,
+        parent: &[String],
+        staged: &[PathBuf],
+        config: Option<String>,
+        references: &[String],
+    ) -> anyhow::Result<String> {
+        if let Some(digest) = parent
+            .iter()
+            .find(|digest| !self.layers.contains_key(*digest))
+        {
+            return Err(anyhow::anyhow!("Layer {} does not exist", digest));
+        }
+
+        let mut new_layers = vec![];
+        let added = self
+            .add_layers(staged, &mut new_layers)
+            .and_then(|digests| {
+                let digests = [parent, &digests].concat();
+                let config = match config {
+                    Some(config) => config,
+                    None => default_config(&digests)?,
+                };
+                let id = format!("{:x}", Sha256::digest(config.as_bytes()));
+                let size = digests
+                    .iter()
+                    .filter_map(|digest| {
+                        self.layers
+                            .get(digest)
+                            .or(new_layers.iter().find(|layer| &layer.digest == digest))
+                    })
+                    .map(|layer| layer.size)
+                    .sum();
+                let image = Image {
+                    id: id.clone(),
+                    references: vec![],
+                    created_at: current_time(),
+                    size,
+                    layers: digests,
+                    config: Some(config),
+                };
+
+                self.update(|store| {
+                    for layer in &new_layers {
+                        store.layers.insert(layer.digest.clone(), layer.clone());
+                    }
+                    store.images.entry(id.clone()).or_insert(image);
+                    for reference in references {
+                        point(&mut store.images, &id, reference);
+                    }
+                })
+                .map(|_| id)
+            });
+
+        if added.is_err() {
+            for layer in &new_layers {
+                let _ = std::fs::remove_dir_all(layer_dir(&layer.digest));
+            }
+        }
+
+        added
+    }
+
+    /// Unpack the tarballs at `staged` not seen yet into `new_layers`, returning their digests.
+    fn add_layers(
+        &self,
+        staged: &[PathBuf],
+        new_layers: &mut Vec<Layer>,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut digests = vec![];
+
+        for staged in staged {
+            let digest = digest_file(staged)?;
+            let known = self.layers.contains_key(&digest)
+                || new_layers.iter().any(|layer| layer.digest == digest);
+            if known {
+                let _ = std::fs::remove_file(staged);
+            } else {
+                new_layers.push(unpack_layer(staged, &digest)?);
+            }
+
+            digests.push(digest);
+        }
+
+        Ok(digests)
+    }
+
+    /// Take the layers of image `name` for a new container.
+    fn acquire(&mut self, name: &str) -> anyhow::Result<Image> {
+        let image = self.resolve(name)?.clone();
+
+        self.update(|store| {
+            for digest in &image.layers {
+                if let Some(layer) = store.layers.get_mut(digest) {
+                    layer.ref_count += 1;
+                }
+            }
+        })?;
+
+        Ok(image)
+    }
+
+    /// Give back layers taken by a container, removing those no longer used. Returns the failures.
+    fn release(&mut self, layers: &[String]) -> Vec<String> {
+        let released = self.update(|store| {
+            for digest in layers {
+                if let Some(layer) = store.layers.get_mut(digest) {
+                    layer.ref_count = layer.ref_count.saturating_sub(1);
+                }
+            }
+        });
+
+        match released {
+            Ok(()) => self.remove_unused_layers(layers),
+            Err(e) => vec![format!("cannot release layers: {e}")],
+        }
+    }
+
+    /// Remove those of `layers` neither containers nor images use. Returns the failures.
+    fn remove_unused_layers(&mut self, layers: &[String]) -> Vec<String> {
+        let unused: Vec<_> = layers
+            .iter()
+            .filter(|digest| {
+                self.layers
+                    .get(*digest)
+                    .is_some_and(|layer| layer.ref_count == 0)
+                    && !self
+                        .images
+                        .values()
+                        .any(|image| image.layers.contains(digest))
+            })
+            .cloned()
+            .collect();
+        if unused.is_empty() {
+            return vec![];
+        }
+
+        if let Err(e) =
+            self.update(|store| store.layers.retain(|digest, _| !unused.contains(digest)))
+        {
+            return vec![format!("cannot remove unused layers: {e}")];
+        }
+
+        let mut errors = vec![];
+        for digest in unused {
+            if let Err(e) = std::fs::remove_dir_all(layer_dir(&digest)) {
+                error!("Failed to delete layer {}: {}", digest, e);
+                errors.push(format!("cannot delete layer {digest}: {e}"));
+            }
+        }
+
+        errors
+    }
+
+    /// Remove image `name` or only its reference if the image has others. Removing an image with
+    /// several references, or used by `users`, takes `force`. Returns what was done.
+    fn remove(
+        &mut self,
+        name: &str,
+        force: bool,
+        users: &HashMap<String, Vec<String>>,
+    ) -> anyhow::Result<Vec<String>> {
+        let image = self.resolve(name)?.clone();
+
+        let reference = parse_image_ref(name)
+            .ok()
+            .filter(|reference| image.references.contains(reference));
+        match reference {
+            Some(reference) if image.references.len() > 1 => {
+                self.update(|store| untag(&mut store.images, &reference))?;
+                return Ok(vec![format!("Untagged: {reference}")]);
+            }
+            None if image.references.len() > 1 && !force => {
+                return Err(anyhow::anyhow!(
+                    "image has several references, remove them one by one or use --force"
+                ));
+            }
+            _ => {}
+        }
+
+        if let Some(containers) = users.get(&image.id).filter(|_| !force) {
+            return Err(anyhow::anyhow!(
+                "image is used by containers {}, remove them first or use --force",
+                containers.join(", ")
+            ));
+        }
+
+        self.update(|store| {
+            store.images.remove(&image.id);
+        })?;
+        // The image is gone, its layers only take space unless containers still run on them.
+        for e in self.remove_unused_layers(&image.layers) {
+            error!("Image {} removed, but {}", image.id, e);
+        }
+
+        let mut done: Vec<_> = image
+            .references
+            .iter()
+            .map(|reference| format!("Untagged: {reference}"))
+            .collect();
+        done.push(format!("Deleted: {}", image.id));
+
+        Ok(done)
+    }
+}
+
+/// Point `reference` to image `id`, taking it from the image it pointed to.
+fn point(images: &mut HashMap<String, Image>, id: &str, reference: &str) {
+    untag(images, reference);
+
+    if let Some(image) = images.get_mut(id) {
+        image.references.push(reference.to_string());
+        image.references.sort();
+    }
+}
+
+fn untag(images: &mut HashMap<String, Image>, reference: &str) {
+    for image in images.values_mut() {
+        image.references.retain(|r| r != reference);
+    }
+}
+
+/// Where a tarball is written before being added, within the store so that it can be moved in.
+pub fn staging_tarball() -> PathBuf {
+    Path::new(ROOT_PATH)
+        .join("images")
+        .join(format!("staging-{}.tar", random_id()))
+}
+
+/// Where an archive is unpacked before its layers are added.
+pub fn staging_dir() -> PathBuf {
+    Path::new(ROOT_PATH)
+        .join("images")
+        .join(format!("staging-{}", random_id()))
+}
+
+/// Image by reference or ID.
+pub async fn find_image(name: &str) -> anyhow::Result<Image> {
+    let images_locked = IMAGES.get().unwrap().lock().await;
+
+    images_locked.resolve(name).cloned()
+}
+
+/// Image by reference or ID, with its layers taken for a new container.
+pub async fn acquire_image(name: &str) -> anyhow::Result<Image> {
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    images_locked.acquire(name)
+}
+
+/// Give back the layers of a removed container. Returns the failures.
+pub async fn release_layers(layers: &[String]) -> Vec<String> {
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    images_locked.release(layers)
+}
+
+/// Add the tarball at `staged` as a layer on top of `parent`, making an image with `config` and
+/// `reference`, returning its ID.
+pub async fn add_image(
+    parent: &[String],
+    staged: &Path,
+    config: String,
+    reference: &str,
+) -> anyhow::Result<String> {
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    images_locked.add(
+        parent,
+        &[staged.to_path_buf()],
+        Some(config),
+        &[reference.to_string()],
+    )
+}
+
+pub async fn list_images(mut stream: UnixStream) {
+    let images_locked = IMAGES.get().unwrap().lock().await;
+
+    let mut images: Vec<_> = images_locked.images.values().collect();
+    images.sort_by_key(|image| std::cmp::Reverse(image.created_at));
+
+    let now = current_time();
+    let mut tw = TabWriter::new(vec![]);
+    let _ = tw.write_all(b"REPOSITORY\tTAG\tIMAGE ID\tCREATED\tSIZE\n");
+    for image in images {
+        let references: Vec<_> = match image.references.as_slice() {
+            [] => vec![("<none>", "<none>")],
+            references => references
+                .iter()
+                .filter_map(|reference| reference.rsplit_once(':'))
+                .collect(),
+        };
+
+        for (name, tag) in references {
+            let _ = writeln!(
+                tw,
+                "{}\t{}\t{}\t{}\t{}",
+                name,
+                tag,
+                &image.id[..12],
+                format_age(now.saturating_sub(image.created_at)),
+                format_size(image.size)
+            );
+        }
+    }
+
+    match tw.into_inner() {
+        Ok(data) => {
+            let _ = Msg::OkContent(String::from_utf8(data).unwrap())
+                .send_to(&mut stream)
+                .await;
+        }
+        Err(e) => {
+            error!("Failed to write to tab writer: {}", e);
+
+            let _ = Msg::Err(format!("Failed to write to tab writer: {}", e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+/// Remove images, refusing those used by a container unless forced.
+pub async fn remove_images(args: RmiArgs, mut stream: UnixStream) {
+    // Containers by the image they run.
+    let mut users: HashMap<String, Vec<String>> = HashMap::new();
+    for meta in CONTAINER_METAS.get().unwrap().get_all_metas().await {
+        users.entry(meta.image_id).or_default().push(meta.name);
+    }
+
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    let mut failed = false;
+    let mut lines = vec![];
+    for name in &args.names {
+        match images_locked.remove(name, args.force, &users) {
+            Ok(done) => {
+                info!("[Daemon] Image {} removed", name);
+                lines.extend(done);
+            }
+            Err(e) => {
+                error!("Failed to remove image {}: {}", name, e);
+                lines.push(format!("Failed to remove image {name}: {e}"));
+                failed = true;
+            }
+        }
+    }
+
+    let msg = if failed {
+        Msg::Err(lines.join("\n"))
+    } else {
+        Msg::OkContent(lines.join("\n"))
+    };
+    let _ = msg.send_to(&mut stream).await;
+}
+
+pub async fn tag_image(args: TagArgs, mut stream: UnixStream) {
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+
+    let tagged = images_locked
+        .resolve(&args.source)
+        .map(|image| image.id.clone())
+        .and_then(|id| images_locked.update(|store| point(&mut store.images, &id, &args.target)));
+
+    match tagged {
+        Ok(()) => {
+            info!("[Daemon] Image {} tagged as {}", args.source, args.target);
+            let _ = Msg::OkContent(format!("Image {} tagged as {}", args.source, args.target))
+                .send_to(&mut stream)
+                .await;
+        }
+        Err(e) => {
+            error!("Failed to tag image {}: {}", args.source, e);
+            let _ = Msg::Err(format!("Failed to tag image {}: {}", args.source, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+/// Import a rootfs tarball as a new image, replying with its ID.
+pub async fn import_image(args: ImportArgs, mut stream: UnixStream) {
+    match import(&args).await {
+        Ok(id) => {
+            info!("[Daemon] Image {} imported from {}", id, args.path);
+            let _ = Msg::OkContent(id).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!("Failed to import image from {}: {}", args.path, e);
+            let _ = Msg::Err(format!("Failed to import image from {}: {}", args.path, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+async fn import(args: &ImportArgs) -> anyhow::Result<String> {
+    // Make sure it is a tarball before taking it in.
+    let output = Command::new("tar")
+        .arg("-tf")
+        .arg(&args.path)
+        .stdout(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Not a tarball: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    let staged = staging_tarball();
+    tokio::fs::copy(&args.path, &staged).await?;
+
+    let mut images_locked = IMAGES.get().unwrap().lock().await;
+    images_locked
+        .add(
+            &[],
+            std::slice::from_ref(&staged),
+            None,
+            args.reference.as_slice(),
+        )
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&staged);
+        })
+}
+
+/// Time elapsed, as `3 hours ago`.
+fn format_age(secs: u64) -> String {
+    let (value, unit) = match secs {
+        0..60 => (secs, "second"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+
+    if value == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{value} {unit}s ago")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(id: &str, references: &[&str]) -> Image {
+        Image {
+            id: id.to_string(),
+            references: references.iter().map(|r| r.to_string()).collect(),
+            created_at: 0,
+            size: 0,
+            layers: vec![],
+            config: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let images = Images {
+            images: HashMap::from([
+                ("abc123".to_string(), image("abc123", &["app:latest"])),
+                (
+                    "abd456".to_string(),
+                    image("abd456", &["app:1.0", "web:1.0"]),
+                ),
+            ]),
+            layers: HashMap::new(),
+            path: PathBuf::new(),
+        };
+
+        assert_eq!(images.resolve("app").unwrap().id, "abc123");
+        assert_eq!(images.resolve("web:1.0").unwrap().id, "abd456");
+        assert_eq!(images.resolve("abd").unwrap().id, "abd456");
+        assert!(images.resolve("ab").is_err());
+        assert!(images.resolve("web").is_err());
+        assert!(images.resolve("").is_err());
+    }
+
+    #[test]
+    fn test_point() {
+        let mut images = HashMap::from([
+            ("a".to_string(), image("a", &["app:latest"])),
+            ("b".to_string(), image("b", &[])),
+        ]);
+
+        point(&mut images, "b", "app:latest");
+        assert!(images["a"].references.is_empty());
+        assert_eq!(images["b"].references, vec!["app:latest"]);
+
+        point(&mut images, "b", "app:1.0");
+        assert_eq!(images["b"].references, vec!["app:1.0", "app:latest"]);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(0), "0 seconds ago");
+        assert_eq!(format_age(60), "1 minute ago");
+        assert_eq!(format_age(7200), "2 hours ago");
+        assert_eq!(format_age(86400 * 3), "3 days ago");
+    }
+}
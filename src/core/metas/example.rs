@@ -59,11 +59,12 @@ impl MetadataEventHandler for LoggingEventHandler {
 // Usage example
 pub async fn example_usage() -> anyhow::Result<()> {
     // 1. Create container manager
-    let mut manager = ContainerManager::default().await?;
+    let manager = ContainerManager::default().await?;
 
-    // 2. Add event handler (commented out due to API changes)
-    // TODO: Implement event system
-    // manager.add_event_handler(Box::new(LoggingEventHandler)).await;
+    // 2. Add event handler
+    manager
+        .add_event_handler(Box::new(LoggingEventHandler))
+        .await;
 
     // 3. Create a complete container metadata
     let container_meta = ContainerMeta::new(
@@ -98,7 +99,8 @@ pub async fn example_usage() -> anyhow::Result<()> {
     // 7. Set resource limits
     let resources = ResourceConfig {
         memory_limit: Some(512 * 1024 * 1024), // 512MB
-        cpu_limit: Some(1.0),                  // 1 core
+        memory_swap_limit: None,
+        cpu_limit: Some(1.0), // 1 core
         pids_limit: Some(1000),
         disk_limit: None,
     };
@@ -123,6 +125,7 @@ pub async fn example_usage() -> anyhow::Result<()> {
         destination: "/var/www/data".to_string(),
         mount_type: MountType::Bind,
         read_only: false,
+        size: None,
     };
     manager.add_mount(container_meta.id.clone(), mount).await?;
 
@@ -0,0 +1,555 @@
+//! On-disk format of the container records, in snapshots and WALs.
+//!
+//! bincode is not self-describing, so records written before a field was added cannot be read
+//! with the new types. Files start with [`MAGIC`] and the version of their format, and those of
+//! older versions are read with the types of the time, then migrated. Files without the header
+//! are of version 0, the format before it was added.
+
+use super::{meta::InnerState, storage::StorageOperation};
+
+/// Start of snapshot and WAL files, followed by the format version as a little-endian `u32`.
+pub const MAGIC: &[u8; 8] = b"RTAINMD\0";
+/// Version of the format written.
+pub const VERSION: u32 = 2;
+
+/// Header of the files written.
+pub fn header() -> Vec<u8> {
+    [MAGIC.as_slice(), &VERSION.to_le_bytes()].concat()
+}
+
+/// Format version of `data`, and what follows its header.
+pub fn split_header(data: &[u8]) -> anyhow::Result<(u32, &[u8])> {
+    let Some(rest) = data.strip_prefix(MAGIC.as_slice()) else {
+        return Ok((0, data));
+    };
+
+    let (version, rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or(anyhow::anyhow!("Truncated format header"))?;
+    let version = u32::from_le_bytes(*version);
+    if version > VERSION {
+        return Err(anyhow::anyhow!(
+            "Records of format version {} are newer than supported, up to {}",
+            version,
+            VERSION
+        ));
+    }
+
+    Ok((version, rest))
+}
+
+/// Decode a snapshot of format `version`.
+pub fn decode_state(version: u32, data: &[u8]) -> anyhow::Result<InnerState> {
+    match version {
+        0 => Ok(bincode::deserialize::<v0::InnerState>(data)?.into()),
+        1 => Ok(bincode::deserialize::<v1::InnerState>(data)?.into()),
+        _ => Ok(bincode::deserialize(data)?),
+    }
+}
+
+/// Decode a WAL operation of format `version`.
+pub fn decode_operation(version: u32, data: &[u8]) -> anyhow::Result<StorageOperation> {
+    match version {
+        0 => Ok(bincode::deserialize::<v0::StorageOperation>(data)?.into()),
+        1 => Ok(bincode::deserialize::<v1::StorageOperation>(data)?.into()),
+        _ => Ok(bincode::deserialize(data)?),
+    }
+}
+
+/// Records before the restart policy, health check, exit signal, swap limit, tmpfs size and image
+/// layers were added. Types left unchanged since are shared.
+mod v0 {
+    use std::collections::HashMap;
+
+    use dashmap::DashMap;
+    use serde::Deserialize;
+
+    use crate::core::metas::{
+        meta, ContainerStatus, HealthStatus, MountType, NetworkConfig, RestartPolicy,
+        StorageOperation as Operation,
+    };
+
+    #[derive(Deserialize)]
+    pub struct ContainerState {
+        status: ContainerStatus,
+        pid: Option<i32>,
+        started_at: Option<u64>,
+        finished_at: Option<u64>,
+        exit_code: Option<i32>,
+        error: Option<String>,
+        restart_count: u32,
+        health_status: HealthStatus,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ResourceConfig {
+        memory_limit: Option<u64>,
+        cpu_limit: Option<f64>,
+        pids_limit: Option<u64>,
+        disk_limit: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MountPoint {
+        source: String,
+        destination: String,
+        mount_type: MountType,
+        read_only: bool,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ContainerMeta {
+        id: String,
+        name: String,
+        created_at: u64,
+        updated_at: u64,
+        image: String,
+        command: Vec<String>,
+        args: Vec<String>,
+        working_dir: Option<String>,
+        user: Option<String>,
+        env: HashMap<String, String>,
+        labels: HashMap<String, String>,
+        state: ContainerState,
+        network: Option<NetworkConfig>,
+        resources: ResourceConfig,
+        mounts: Vec<MountPoint>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct InnerState {
+        by_id: DashMap<String, ContainerMeta>,
+        by_name: DashMap<String, String>,
+    }
+
+    #[derive(Deserialize)]
+    pub enum StorageOperation {
+        Create(Box<ContainerMeta>),
+        Delete(String),
+        UpdateStatus {
+            id: String,
+            status: ContainerStatus,
+        },
+        UpdateState {
+            id: String,
+            state: ContainerState,
+        },
+        UpdateEnvironment {
+            id: String,
+            env: HashMap<String, String>,
+        },
+        UpdateLabels {
+            id: String,
+            labels: HashMap<String, String>,
+        },
+        UpdateResources {
+            id: String,
+            resources: ResourceConfig,
+        },
+        AttachNetwork {
+            id: String,
+            network: NetworkConfig,
+        },
+        DetachNetwork {
+            id: String,
+        },
+        AddMount {
+            id: String,
+            mount: MountPoint,
+        },
+        RemoveMount {
+            id: String,
+            destination: String,
+        },
+        Batch(Vec<StorageOperation>),
+    }
+
+    impl From<ContainerState> for meta::ContainerState {
+        fn from(state: ContainerState) -> Self {
+            Self {
+                status: state.status,
+                pid: state.pid,
+                started_at: state.started_at,
+                finished_at: state.finished_at,
+                exit_code: state.exit_code,
+                signal: None,
+                error: state.error,
+                restart_count: state.restart_count,
+                health_status: state.health_status,
+            }
+        }
+    }
+
+    impl From<ResourceConfig> for meta::ResourceConfig {
+        fn from(resources: ResourceConfig) -> Self {
+            Self {
+                memory_limit: resources.memory_limit,
+                memory_swap_limit: None,
+                cpu_limit: resources.cpu_limit,
+                pids_limit: resources.pids_limit,
+                disk_limit: resources.disk_limit,
+            }
+        }
+    }
+
+    impl From<MountPoint> for meta::MountPoint {
+        fn from(mount: MountPoint) -> Self {
+            Self {
+                source: mount.source,
+                destination: mount.destination,
+                mount_type: mount.mount_type,
+                read_only: mount.read_only,
+                size: None,
+            }
+        }
+    }
+
+    impl From<ContainerMeta> for meta::ContainerMeta {
+        fn from(meta: ContainerMeta) -> Self {
+            // The image was unpacked into the workspace then, so there are no layers to take.
+            Self {
+                id: meta.id,
+                name: meta.name,
+                created_at: meta.created_at,
+                created_at_nanos: 0,
+                updated_at: meta.updated_at,
+                image: meta.image,
+                image_id: String::new(),
+                layers: vec![],
+                command: meta.command,
+                args: meta.args,
+                working_dir: meta.working_dir,
+                user: meta.user,
+                env: meta.env,
+                labels: meta.labels,
+                state: meta.state.into(),
+                network: meta.network,
+                resources: meta.resources.into(),
+                mounts: meta.mounts.into_iter().map(Into::into).collect(),
+                restart_policy: RestartPolicy::No,
+                health_check: None,
+            }
+        }
+    }
+
+    impl From<InnerState> for meta::InnerState {
+        fn from(state: InnerState) -> Self {
+            Self {
+                by_id: state
+                    .by_id
+                    .into_iter()
+                    .map(|(id, meta)| (id, meta.into()))
+                    .collect(),
+                by_name: state.by_name,
+            }
+        }
+    }
+
+    impl From<StorageOperation> for Operation {
+        fn from(op: StorageOperation) -> Self {
+            match op {
+                StorageOperation::Create(meta) => Self::Create((*meta).into()),
+                StorageOperation::Delete(id) => Self::Delete(id),
+                StorageOperation::UpdateStatus { id, status } => Self::UpdateStatus { id, status },
+                StorageOperation::UpdateState { id, state } => Self::UpdateState {
+                    id,
+                    state: state.into(),
+                },
+                StorageOperation::UpdateEnvironment { id, env } => {
+                    Self::UpdateEnvironment { id, env }
+                }
+                StorageOperation::UpdateLabels { id, labels } => Self::UpdateLabels { id, labels },
+                StorageOperation::UpdateResources { id, resources } => Self::UpdateResources {
+                    id,
+                    resources: resources.into(),
+                },
+                StorageOperation::AttachNetwork { id, network } => {
+                    Self::AttachNetwork { id, network }
+                }
+                StorageOperation::DetachNetwork { id } => Self::DetachNetwork { id },
+                StorageOperation::AddMount { id, mount } => Self::AddMount {
+                    id,
+                    mount: mount.into(),
+                },
+                StorageOperation::RemoveMount { id, destination } => {
+                    Self::RemoveMount { id, destination }
+                }
+                StorageOperation::Batch(ops) => {
+                    Self::Batch(ops.into_iter().map(Into::into).collect())
+                }
+            }
+        }
+    }
+}
+
+/// Records before the creation time was kept to the nanosecond.
+mod v1 {
+    use std::collections::HashMap;
+
+    use dashmap::DashMap;
+    use serde::Deserialize;
+
+    use crate::core::metas::{
+        meta, ContainerState, ContainerStatus, HealthCheckConfig, HealthStatus, MountPoint,
+        NetworkConfig, ResourceConfig, RestartPolicy, StorageOperation as Operation,
+    };
+
+    #[derive(Deserialize)]
+    pub struct ContainerMeta {
+        id: String,
+        name: String,
+        created_at: u64,
+        updated_at: u64,
+        image: String,
+        image_id: String,
+        layers: Vec<String>,
+        command: Vec<String>,
+        args: Vec<String>,
+        working_dir: Option<String>,
+        user: Option<String>,
+        env: HashMap<String, String>,
+        labels: HashMap<String, String>,
+        state: ContainerState,
+        network: Option<NetworkConfig>,
+        resources: ResourceConfig,
+        mounts: Vec<MountPoint>,
+        restart_policy: RestartPolicy,
+        health_check: Option<HealthCheckConfig>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct InnerState {
+        by_id: DashMap<String, ContainerMeta>,
+        by_name: DashMap<String, String>,
+    }
+
+    #[derive(Deserialize)]
+    pub enum StorageOperation {
+        Create(Box<ContainerMeta>),
+        Delete(String),
+        UpdateStatus {
+            id: String,
+            status: ContainerStatus,
+        },
+        UpdateState {
+            id: String,
+            state: ContainerState,
+        },
+        UpdateHealth {
+            id: String,
+            health_status: HealthStatus,
+        },
+        UpdateEnvironment {
+            id: String,
+            env: HashMap<String, String>,
+        },
+        UpdateLabels {
+            id: String,
+            labels: HashMap<String, String>,
+        },
+        UpdateResources {
+            id: String,
+            resources: ResourceConfig,
+        },
+        AttachNetwork {
+            id: String,
+            network: NetworkConfig,
+        },
+        DetachNetwork {
+            id: String,
+        },
+        AddMount {
+            id: String,
+            mount: MountPoint,
+        },
+        RemoveMount {
+            id: String,
+            destination: String,
+        },
+        Batch(Vec<StorageOperation>),
+    }
+
+    impl From<ContainerMeta> for meta::ContainerMeta {
+        fn from(meta: ContainerMeta) -> Self {
+            Self {
+                id: meta.id,
+                name: meta.name,
+                created_at: meta.created_at,
+                created_at_nanos: 0,
+                updated_at: meta.updated_at,
+                image: meta.image,
+                image_id: meta.image_id,
+                layers: meta.layers,
+                command: meta.command,
+                args: meta.args,
+                working_dir: meta.working_dir,
+                user: meta.user,
+                env: meta.env,
+                labels: meta.labels,
+                state: meta.state,
+                network: meta.network,
+                resources: meta.resources,
+                mounts: meta.mounts,
+                restart_policy: meta.restart_policy,
+                health_check: meta.health_check,
+            }
+        }
+    }
+
+    impl From<InnerState> for meta::InnerState {
+        fn from(state: InnerState) -> Self {
+            Self {
+                by_id: state
+                    .by_id
+                    .into_iter()
+                    .map(|(id, meta)| (id, meta.into()))
+                    .collect(),
+                by_name: state.by_name,
+            }
+        }
+    }
+
+    impl From<StorageOperation> for Operation {
+        fn from(op: StorageOperation) -> Self {
+            match op {
+                StorageOperation::Create(meta) => Self::Create((*meta).into()),
+                StorageOperation::Delete(id) => Self::Delete(id),
+                StorageOperation::UpdateStatus { id, status } => Self::UpdateStatus { id, status },
+                StorageOperation::UpdateState { id, state } => Self::UpdateState { id, state },
+                StorageOperation::UpdateHealth { id, health_status } => {
+                    Self::UpdateHealth { id, health_status }
+                }
+                StorageOperation::UpdateEnvironment { id, env } => {
+                    Self::UpdateEnvironment { id, env }
+                }
+                StorageOperation::UpdateLabels { id, labels } => Self::UpdateLabels { id, labels },
+                StorageOperation::UpdateResources { id, resources } => {
+                    Self::UpdateResources { id, resources }
+                }
+                StorageOperation::AttachNetwork { id, network } => {
+                    Self::AttachNetwork { id, network }
+                }
+                StorageOperation::DetachNetwork { id } => Self::DetachNetwork { id },
+                StorageOperation::AddMount { id, mount } => Self::AddMount { id, mount },
+                StorageOperation::RemoveMount { id, destination } => {
+                    Self::RemoveMount { id, destination }
+                }
+                StorageOperation::Batch(ops) => {
+                    Self::Batch(ops.into_iter().map(Into::into).collect())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::metas::{ContainerMeta, ContainerStatus};
+
+    #[test]
+    fn test_split_header() {
+        let mut data = header();
+        data.extend_from_slice(b"records");
+        assert_eq!(
+            split_header(&data).unwrap(),
+            (VERSION, b"records".as_slice())
+        );
+
+        // Written before the header was added.
+        assert_eq!(
+            split_header(b"records").unwrap(),
+            (0, b"records".as_slice())
+        );
+
+        let mut newer = MAGIC.to_vec();
+        newer.extend_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(split_header(&newer).is_err());
+        assert!(split_header(MAGIC).is_err());
+    }
+
+    #[test]
+    fn test_decode_version_0() {
+        // A record as bincode wrote it in version 0, field by field.
+        let mut data = vec![];
+        let mut string = |data: &mut Vec<u8>, s: &str| {
+            data.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            data.extend_from_slice(s.as_bytes());
+        };
+        data.extend_from_slice(&0u32.to_le_bytes()); // Create
+        string(&mut data, "id0");
+        string(&mut data, "web");
+        data.extend_from_slice(&1u64.to_le_bytes()); // created_at
+        data.extend_from_slice(&2u64.to_le_bytes()); // updated_at
+        string(&mut data, "busybox");
+        data.extend_from_slice(&1u64.to_le_bytes()); // command
+        string(&mut data, "sh");
+        data.extend_from_slice(&0u64.to_le_bytes()); // args
+        data.extend_from_slice(&[0, 0]); // working_dir, user
+        data.extend_from_slice(&0u64.to_le_bytes()); // env
+        data.extend_from_slice(&0u64.to_le_bytes()); // labels
+        data.extend_from_slice(&5u32.to_le_bytes()); // status, Exited
+        data.extend_from_slice(&[0, 0, 0]); // pid, started_at, finished_at
+        data.push(1); // exit_code
+        data.extend_from_slice(&3i32.to_le_bytes());
+        data.push(0); // error
+        data.extend_from_slice(&0u32.to_le_bytes()); // restart_count
+        data.extend_from_slice(&0u32.to_le_bytes()); // health_status
+        data.push(0); // network
+        data.push(1); // memory_limit
+        data.extend_from_slice(&1024u64.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0]); // cpu_limit, pids_limit, disk_limit
+        data.extend_from_slice(&0u64.to_le_bytes()); // mounts
+
+        let StorageOperation::Create(meta) = decode_operation(0, &data).unwrap() else {
+            panic!("not a create operation");
+        };
+        let ContainerMeta {
+            name,
+            state,
+            resources,
+            layers,
+            health_check,
+            ..
+        } = meta;
+        assert_eq!(name, "web");
+        assert_eq!(state.status, ContainerStatus::Exited);
+        assert_eq!(state.exit_code, Some(3));
+        assert_eq!(state.signal, None);
+        assert_eq!(resources.memory_limit, Some(1024));
+        assert_eq!(resources.memory_swap_limit, None);
+        assert!(layers.is_empty());
+        assert!(health_check.is_none());
+
+        assert!(decode_operation(VERSION, &data).is_err());
+    }
+
+    #[test]
+    fn test_decode_version_1() {
+        let mut meta = ContainerMeta::new(
+            "id0".to_string(),
+            "web".to_string(),
+            "busybox".to_string(),
+            vec!["sh".to_string()],
+            vec![],
+        );
+        meta.layers = vec!["sha256:0".to_string()];
+
+        // Version 1 is the current one without the nanoseconds after the creation time.
+        let mut data = bincode::serialize(&StorageOperation::Create(meta.clone())).unwrap();
+        let nanos = 4 + (8 + 3) + (8 + 3) + 8;
+        data.drain(nanos..nanos + 4);
+
+        let StorageOperation::Create(decoded) = decode_operation(1, &data).unwrap() else {
+            panic!("not a create operation");
+        };
+        assert_eq!(decoded.created_at_nanos, 0);
+        assert_eq!(
+            decoded,
+            ContainerMeta {
+                created_at_nanos: 0,
+                ..meta
+            }
+        );
+    }
+}
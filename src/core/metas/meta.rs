@@ -36,6 +36,7 @@ pub struct ContainerState {
     pub started_at: Option<u64>,
     pub finished_at: Option<u64>,
     pub exit_code: Option<i32>,
+    pub signal: Option<String>, // signal which killed the process
     pub error: Option<String>,
     pub restart_count: u32,
     pub health_status: HealthStatus,
@@ -49,20 +50,22 @@ pub struct NetworkConfig {
     pub ports: HashMap<u16, u16>, // host_port -> container_port
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct ResourceConfig {
-    pub memory_limit: Option<u64>, // bytes
-    pub cpu_limit: Option<f64>,    // cores
+    pub memory_limit: Option<u64>,      // bytes
+    pub memory_swap_limit: Option<u64>, // bytes, memory plus swap
+    pub cpu_limit: Option<f64>,         // cores
     pub pids_limit: Option<u64>,
     pub disk_limit: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct MountPoint {
-    pub source: String,      // host path
+    pub source: String,      // host path, or volume name
     pub destination: String, // container path
     pub mount_type: MountType,
     pub read_only: bool,
+    pub size: Option<u64>, // bytes, tmpfs only
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -72,16 +75,38 @@ pub enum MountType {
     Tmpfs,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthCheckConfig {
+    pub command: Vec<String>,
+    pub interval: u64,     // seconds
+    pub retries: u32,      // failures in a row to be unhealthy
+    pub start_period: u64, // seconds, failures do not count within
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    OnFailure {
+        max_retries: Option<u32>,
+    },
+    Always,
+    UnlessStopped,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ContainerMeta {
     // Basic information
     pub id: String,
     pub name: String,
     pub created_at: u64,
+    pub created_at_nanos: u32, // sub-second part of created_at, to order by creation
     pub updated_at: u64,
 
     // Configuration information
     pub image: String,
+    pub image_id: String,
+    pub layers: Vec<String>, // digests of the image layers, bottom first
     pub command: Vec<String>,
     pub args: Vec<String>,
     pub working_dir: Option<String>,
@@ -102,6 +127,12 @@ pub struct ContainerMeta {
 
     // Mount information
     pub mounts: Vec<MountPoint>,
+
+    // Restart policy
+    pub restart_policy: RestartPolicy,
+
+    // Health check
+    pub health_check: Option<HealthCheckConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -150,6 +181,24 @@ impl ContainerManager {
             .await
     }
 
+    #[inline]
+    pub async fn update_state(&self, id: String, state: ContainerState) -> anyhow::Result<()> {
+        self.storage
+            .execute(StorageOperation::UpdateState { id, state })
+            .await
+    }
+
+    #[inline]
+    pub async fn update_health(
+        &self,
+        id: String,
+        health_status: HealthStatus,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .execute(StorageOperation::UpdateHealth { id, health_status })
+            .await
+    }
+
     // Enhanced query functionality
     pub async fn list_containers(&self, filter: Option<ContainerFilter>) -> Vec<ContainerMeta> {
         let all_metas = self.storage.get_all_metas().await;
@@ -163,6 +212,12 @@ impl ContainerManager {
             .filter(|meta| filter.matches(meta))
             .collect();
 
+        // Newest first, so a limit keeps the latest ones.
+        filtered.sort_by(|a, b| {
+            b.creation()
+                .cmp(&a.creation())
+                .then_with(|| a.name.cmp(&b.name))
+        });
         if let Some(limit) = filter.limit {
             filtered.truncate(limit);
         }
@@ -228,10 +283,22 @@ impl ContainerManager {
             .await
     }
 
-    // Event system support temporarily removed for compilation
-    // TODO: Implement event system properly
+    // Event system support
+    pub async fn add_event_handler(&self, handler: Box<dyn MetadataEventHandler>) {
+        self.storage.add_event_handler(handler).await
+    }
 
     // Advanced container management methods
+    pub async fn update_labels(
+        &self,
+        id: String,
+        labels: HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        self.storage
+            .execute(StorageOperation::UpdateLabels { id, labels })
+            .await
+    }
+
     pub async fn update_container_resources(
         &self,
         id: String,
@@ -276,7 +343,6 @@ impl ContainerManager {
     }
 
     #[inline]
-    #[allow(unused)]
     pub async fn get_meta_by_id(&self, id: &str) -> Option<ContainerMeta> {
         self.storage.get_meta_by_id(id).await
     }
@@ -300,13 +366,18 @@ impl ContainerMeta {
         command: Vec<String>,
         args: Vec<String>,
     ) -> Self {
-        let time = current_time();
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
         Self {
             id,
             name,
-            created_at: time,
-            updated_at: time,
+            created_at: created.as_secs(),
+            created_at_nanos: created.subsec_nanos(),
+            updated_at: created.as_secs(),
             image,
+            image_id: String::new(),
+            layers: Vec::new(),
             command,
             args,
             working_dir: None,
@@ -319,29 +390,42 @@ impl ContainerMeta {
                 started_at: None,
                 finished_at: None,
                 exit_code: None,
+                signal: None,
                 error: None,
                 restart_count: 0,
                 health_status: HealthStatus::Unknown,
             },
             network: None,
-            resources: ResourceConfig {
-                memory_limit: None,
-                cpu_limit: None,
-                pids_limit: None,
-                disk_limit: None,
-            },
+            resources: ResourceConfig::default(),
             mounts: Vec::new(),
+            restart_policy: RestartPolicy::No,
+            health_check: None,
         }
     }
 
+    /// The command followed by its args, as run.
+    pub fn command_line(&self) -> Vec<String> {
+        self.command.iter().chain(&self.args).cloned().collect()
+    }
+
     pub fn get_pid(&self) -> Option<i32> {
         self.state.pid
     }
 
+    /// When the container was created, ordered to the nanosecond.
+    pub fn creation(&self) -> (u64, u32) {
+        (self.created_at, self.created_at_nanos)
+    }
+
     pub fn set_running(&mut self, pid: i32) {
         self.state.status = ContainerStatus::Running;
         self.state.pid = Some(pid);
         self.state.started_at = Some(current_time());
+        // Results of the last run no longer apply.
+        self.state.exit_code = None;
+        self.state.signal = None;
+        self.state.error = None;
+        self.state.health_status = HealthStatus::Unknown;
         self.updated_at = current_time();
     }
 
@@ -350,7 +434,10 @@ impl ContainerMeta {
         self.state.pid = None;
         self.state.finished_at = Some(current_time());
         self.state.exit_code = exit_code;
+        self.state.signal = None;
         self.state.error = error;
+        // Checks only run while the container does.
+        self.state.health_status = HealthStatus::Unknown;
         self.updated_at = current_time();
     }
 }
@@ -373,6 +460,74 @@ impl ContainerStatus {
     }
 }
 
+impl std::str::FromStr for ContainerStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "creating" => Ok(Self::Creating),
+            "running" => Ok(Self::Running),
+            "paused" => Ok(Self::Paused),
+            "restarting" => Ok(Self::Restarting),
+            "removing" => Ok(Self::Removing),
+            "exited" => Ok(Self::Exited),
+            "dead" => Ok(Self::Dead),
+            _ => Err(format!("Invalid container status: {s}")),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Whether a container which exited with `exit_code` should be restarted, given it has been
+    /// restarted `restart_count` times. An unknown exit code counts as a failure.
+    pub fn should_restart(&self, exit_code: Option<i32>, restart_count: u32) -> bool {
+        match self {
+            Self::No => false,
+            Self::OnFailure { max_retries } => {
+                exit_code != Some(0) && max_retries.is_none_or(|max| restart_count < max)
+            }
+            Self::Always | Self::UnlessStopped => true,
+        }
+    }
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None => match s {
+                "no" => Ok(Self::No),
+                "on-failure" => Ok(Self::OnFailure { max_retries: None }),
+                "always" => Ok(Self::Always),
+                "unless-stopped" => Ok(Self::UnlessStopped),
+                _ => Err(format!("Invalid restart policy: {s}")),
+            },
+            Some(("on-failure", max_retries)) => match max_retries.parse() {
+                Ok(max_retries) => Ok(Self::OnFailure {
+                    max_retries: Some(max_retries),
+                }),
+                Err(_) => Err(format!("Invalid restart retries: {max_retries}")),
+            },
+            Some(_) => Err(format!("Invalid restart policy: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::No => write!(f, "no"),
+            Self::OnFailure { max_retries: None } => write!(f, "on-failure"),
+            Self::OnFailure {
+                max_retries: Some(max_retries),
+            } => write!(f, "on-failure:{max_retries}"),
+            Self::Always => write!(f, "always"),
+            Self::UnlessStopped => write!(f, "unless-stopped"),
+        }
+    }
+}
+
 impl InnerState {
     pub fn apply_operation(&self, op: StorageOperation) -> anyhow::Result<()> {
         match op {
@@ -397,6 +552,12 @@ impl InnerState {
                     entry.updated_at = current_time();
                 }
             }
+            StorageOperation::UpdateHealth { id, health_status } => {
+                if let Some(mut entry) = self.by_id.get_mut(&id) {
+                    entry.state.health_status = health_status;
+                    entry.updated_at = current_time();
+                }
+            }
             StorageOperation::UpdateEnvironment { id, env } => {
                 if let Some(mut entry) = self.by_id.get_mut(&id) {
                     entry.env = env;
@@ -550,6 +711,20 @@ pub trait MetadataEventHandler: Send + Sync {
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + '_>>;
 }
 
+/// Event handler used by the daemon, which records events into the log.
+pub struct LogEventHandler;
+
+impl MetadataEventHandler for LogEventHandler {
+    fn handle(
+        &self,
+        event: MetadataEvent,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            log::info!("[Daemon] Metadata event: {:?}", event);
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -604,6 +779,7 @@ mod tests {
         assert_eq!(meta.image, "nginx:latest");
         assert_eq!(meta.command, vec!["nginx"]);
         assert_eq!(meta.args, vec!["-g", "daemon off;"]);
+        assert_eq!(meta.command_line(), vec!["nginx", "-g", "daemon off;"]);
         assert_eq!(meta.state.status, ContainerStatus::Creating);
         assert!(meta.env.is_empty());
         assert!(meta.labels.is_empty());
@@ -637,6 +813,59 @@ mod tests {
         meta.set_stopped(Some(1), Some("Error occurred".to_string()));
         assert_eq!(meta.state.exit_code, Some(1));
         assert_eq!(meta.state.error, Some("Error occurred".to_string()));
+
+        // Test restarting clears the last results
+        let finished_at = meta.state.finished_at;
+        meta.set_running(5678);
+        assert_eq!(meta.state.exit_code, None);
+        assert_eq!(meta.state.error, None);
+        assert_eq!(meta.state.finished_at, finished_at);
+    }
+
+    #[test]
+    fn test_container_status_from_str() {
+        assert_eq!("running".parse(), Ok(ContainerStatus::Running));
+        assert_eq!("Exited".parse(), Ok(ContainerStatus::Exited));
+        assert!("stopped".parse::<ContainerStatus>().is_err());
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        assert_eq!("no".parse(), Ok(RestartPolicy::No));
+        assert_eq!("always".parse(), Ok(RestartPolicy::Always));
+        assert_eq!("unless-stopped".parse(), Ok(RestartPolicy::UnlessStopped));
+        assert_eq!(
+            "on-failure".parse(),
+            Ok(RestartPolicy::OnFailure { max_retries: None })
+        );
+        assert_eq!(
+            "on-failure:3".parse(),
+            Ok(RestartPolicy::OnFailure {
+                max_retries: Some(3)
+            })
+        );
+        assert!("on-failure:x".parse::<RestartPolicy>().is_err());
+        assert!("always:3".parse::<RestartPolicy>().is_err());
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+        assert_eq!(
+            RestartPolicy::OnFailure {
+                max_retries: Some(3)
+            }
+            .to_string(),
+            "on-failure:3"
+        );
+
+        assert!(!RestartPolicy::No.should_restart(Some(1), 0));
+        assert!(RestartPolicy::Always.should_restart(Some(0), 10));
+        assert!(RestartPolicy::UnlessStopped.should_restart(None, 10));
+
+        let on_failure = RestartPolicy::OnFailure {
+            max_retries: Some(2),
+        };
+        assert!(!on_failure.should_restart(Some(0), 0));
+        assert!(on_failure.should_restart(Some(1), 1));
+        assert!(on_failure.should_restart(None, 1));
+        assert!(!on_failure.should_restart(Some(1), 2));
     }
 
     #[test]
@@ -730,6 +959,7 @@ mod tests {
     fn test_resource_config() {
         let resources = ResourceConfig {
             memory_limit: Some(512 * 1024 * 1024), // 512MB
+            memory_swap_limit: None,
             cpu_limit: Some(1.5),
             pids_limit: Some(1000),
             disk_limit: None,
@@ -763,6 +993,7 @@ mod tests {
             destination: "/app/data".to_string(),
             mount_type: MountType::Bind,
             read_only: false,
+            size: None,
         };
 
         assert_eq!(mount.source, "/host/data");
@@ -822,6 +1053,59 @@ mod tests {
         assert!(deleted.is_none());
     }
 
+    #[tokio::test]
+    async fn test_event_handlers() {
+        let temp_wal = TempDir::new().unwrap();
+        let temp_snapshots = TempDir::new().unwrap();
+
+        let config = StorageConfig {
+            wal_dir: temp_wal.path().to_path_buf(),
+            snapshots_dir: temp_snapshots.path().to_path_buf(),
+            max_wals: 5,
+            max_snapshots: 3,
+            snapshot_intervals_secs: 60,
+            cleanup_interval_secs: 180,
+        };
+
+        let manager = ContainerManager::new(config).await.unwrap();
+        let handler = TestEventHandler::new();
+        let events = handler.events.clone();
+        manager.add_event_handler(Box::new(handler)).await;
+
+        let meta = ContainerMeta::new(
+            "test_id".to_string(),
+            "test_container".to_string(),
+            "nginx:latest".to_string(),
+            vec!["nginx".to_string()],
+            vec![],
+        );
+        manager.register(meta.clone()).await.unwrap();
+
+        let resources = ResourceConfig {
+            memory_limit: Some(256 * 1024 * 1024),
+            ..Default::default()
+        };
+        manager
+            .update_container_resources(meta.id.clone(), resources.clone())
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            vec![
+                MetadataEvent::ContainerCreated {
+                    id: meta.id.clone(),
+                    name: meta.name.clone(),
+                },
+                MetadataEvent::ResourcesUpdated {
+                    id: meta.id.clone(),
+                    resources,
+                },
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_advanced_queries() {
         let temp_wal = TempDir::new().unwrap();
@@ -1,3 +1,4 @@
+mod format;
 mod meta;
 mod snapshot;
 mod storage;
@@ -7,8 +8,8 @@ pub mod example;
 
 pub use meta::{
     ContainerFilter, ContainerManager, ContainerMeta, ContainerState, ContainerStatus,
-    HealthStatus, MetadataEvent, MetadataEventHandler, MountPoint, MountType, NetworkConfig,
-    ResourceConfig, ResourceSummary,
+    HealthCheckConfig, HealthStatus, LogEventHandler, MetadataEvent, MetadataEventHandler,
+    MountPoint, MountType, NetworkConfig, ResourceConfig, ResourceSummary, RestartPolicy,
 };
 pub use storage::{StorageConfig, StorageManager, StorageOperation};
 use tokio::sync::OnceCell;
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use super::{current_time, meta::InnerState};
+use super::{current_time, format, meta::InnerState};
 
 #[derive(Debug)]
 pub struct Snapshotter {
@@ -23,7 +23,8 @@ impl Snapshotter {
             .snapshot_dir
             .join(format!("snapshot-{}.bin", current_time()));
 
-        let data = bincode::serialize(state)?;
+        let mut data = format::header();
+        data.extend(bincode::serialize(state)?);
         tokio::fs::write(&tmp_path, &data).await?;
         tokio::fs::rename(tmp_path, final_path).await?;
 
@@ -40,7 +41,8 @@ impl Snapshotter {
 
         if let Some(entry) = entries.last() {
             let data = tokio::fs::read(entry.path()).await?;
-            Ok(bincode::deserialize(&data)?)
+            let (version, data) = format::split_header(&data)?;
+            format::decode_state(version, data)
         } else {
             Ok(InnerState::default())
         }
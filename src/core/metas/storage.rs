@@ -2,13 +2,14 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use serde::{Deserialize, Serialize};
 use tokio::{
-    sync::{mpsc, oneshot, Mutex},
+    sync::{mpsc, oneshot, Mutex, RwLock},
     task::JoinHandle,
 };
 
 use super::{
+    format,
     meta::{
-        ContainerMeta, ContainerState, ContainerStatus, InnerState, MetadataEvent,
+        ContainerMeta, ContainerState, ContainerStatus, HealthStatus, InnerState, MetadataEvent,
         MetadataEventHandler, MountPoint, MountType, NetworkConfig, ResourceConfig,
     },
     snapshot::Snapshotter,
@@ -30,6 +31,7 @@ pub struct StorageConfig {
 pub struct StorageManager {
     op_sender: Arc<Mutex<mpsc::Sender<(StorageOperation, oneshot::Sender<anyhow::Result<()>>)>>>,
     inner: Arc<Mutex<StorageInner>>,
+    event_handlers: RwLock<Vec<Box<dyn MetadataEventHandler>>>,
     #[allow(unused)]
     worker: JoinHandle<()>,
 }
@@ -39,6 +41,10 @@ impl std::fmt::Debug for StorageManager {
         f.debug_struct("StorageManager")
             .field("op_sender", &"Arc<Mutex<Sender>>")
             .field("inner", &self.inner)
+            .field(
+                "event_handlers",
+                &"RwLock<Vec<Box<dyn MetadataEventHandler>>>",
+            )
             .field("worker", &"JoinHandle<()>")
             .finish()
     }
@@ -67,6 +73,10 @@ pub enum StorageOperation {
         id: String,
         state: ContainerState,
     },
+    UpdateHealth {
+        id: String,
+        health_status: HealthStatus,
+    },
 
     // Configuration updates
     UpdateEnvironment {
@@ -124,16 +134,25 @@ impl StorageManager {
         Ok(Self {
             inner: inner,
             op_sender: Arc::new(Mutex::new(op_sender)),
+            event_handlers: RwLock::new(Vec::new()),
             worker,
         })
     }
 
     pub async fn execute(&self, op: StorageOperation) -> anyhow::Result<()> {
+        // Events may depend on the old state, so form them before applying.
+        let event = self.operation_to_event(&op).await;
         let (ack_tx, ack_rx) = oneshot::channel();
 
         self.op_sender.lock().await.send((op, ack_tx)).await?;
 
-        ack_rx.await?
+        ack_rx.await??;
+
+        if let Some(event) = event {
+            self.emit_event(event).await;
+        }
+
+        Ok(())
     }
 
     async fn recover_state(
@@ -148,6 +167,17 @@ impl StorageManager {
             state.apply_operation(op)?;
         }
 
+        // Records are appended in the current format, so migrate a WAL of an older one by
+        // snapshotting what it holds and archiving it.
+        if wal.is_legacy().await? {
+            log::info!(
+                "Migrating container metas to format version {}",
+                format::VERSION
+            );
+            snapshotter.take_snapshot(&state).await?;
+            wal.rotate().await?;
+        }
+
         Ok(state)
     }
 
@@ -187,7 +217,7 @@ impl StorageManager {
                 interval.tick().await;
                 loop {
                     interval.tick().await;
-                    let mut locked_inner = cleanup_inner.lock().await;
+                    let locked_inner = cleanup_inner.lock().await;
 
                     let cleanup_result: anyhow::Result<()> = async {
                         locked_inner.snapshotter.purge_old_snapshots().await?;
@@ -274,8 +304,15 @@ impl StorageManager {
     }
 
     // Event system support
-    // Event handler methods temporarily removed for compilation
-    // TODO: Implement event system properly
+    pub async fn add_event_handler(&self, handler: Box<dyn MetadataEventHandler>) {
+        self.event_handlers.write().await.push(handler);
+    }
+
+    async fn emit_event(&self, event: MetadataEvent) {
+        for handler in self.event_handlers.read().await.iter() {
+            handler.handle(event.clone()).await;
+        }
+    }
 
     async fn operation_to_event(&self, op: &StorageOperation) -> Option<MetadataEvent> {
         match op {
@@ -311,6 +348,17 @@ impl StorageManager {
                     resources: resources.clone(),
                 })
             }
+            StorageOperation::UpdateHealth { id, health_status } => {
+                if let Some(meta) = self.get_meta_by_id(id).await {
+                    Some(MetadataEvent::HealthChanged {
+                        id: id.clone(),
+                        old_health: meta.state.health_status,
+                        new_health: health_status.clone(),
+                    })
+                } else {
+                    None
+                }
+            }
             StorageOperation::AttachNetwork { id, network } => {
                 Some(MetadataEvent::NetworkAttached {
                     id: id.clone(),
@@ -599,6 +647,7 @@ mod tests {
         // Test resource configuration updates
         let resources = ResourceConfig {
             memory_limit: Some(512 * 1024 * 1024),
+            memory_swap_limit: None,
             cpu_limit: Some(1.5),
             pids_limit: Some(1000),
             disk_limit: None,
@@ -616,6 +665,19 @@ mod tests {
             assert_eq!(updated.resources.cpu_limit, Some(1.5));
         } // Reference is dropped here
 
+        // Test health updates
+        let update_health_op = StorageOperation::UpdateHealth {
+            id: meta.id.clone(),
+            health_status: HealthStatus::Healthy,
+        };
+        state.apply_operation(update_health_op).unwrap();
+
+        // Verify health update - use scoped block to release reference
+        {
+            let updated = state.by_id.get(&meta.id).unwrap();
+            assert_eq!(updated.state.health_status, HealthStatus::Healthy);
+        } // Reference is dropped here
+
         // Test network operations
         let network = NetworkConfig {
             ip_address: Some("172.17.0.2".to_string()),
@@ -657,6 +719,7 @@ mod tests {
             destination: "/app/data".to_string(),
             mount_type: MountType::Bind,
             read_only: false,
+            size: None,
         };
         let add_mount_op = StorageOperation::AddMount {
             id: meta.id.clone(),
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use tokio::io::AsyncWriteExt;
 // Note: Serde imports removed as they're not used in this file
 
-use super::{current_time, storage::StorageOperation};
+use super::{current_time, format, storage::StorageOperation};
 
 #[derive(Debug)]
 /// Write-ahead loggings.
@@ -15,11 +15,12 @@ pub struct WalManager {
 
 impl WalManager {
     pub async fn new(wal_dir: &PathBuf, max_wals: usize) -> anyhow::Result<Self> {
-        tokio::fs::create_dir_all(&wal_dir).await?;
+        let archive_dir = wal_dir.join("archive");
+        tokio::fs::create_dir_all(&archive_dir).await?;
 
         Ok(Self {
             current_path: wal_dir.join("current.wal"),
-            archive_dir: wal_dir.join("archive"),
+            archive_dir,
             max_archives: max_wals,
         })
     }
@@ -31,6 +32,11 @@ impl WalManager {
             .open(&self.current_path)
             .await?;
 
+        // A new file starts with the header of the format.
+        if file.metadata().await?.len() == 0 {
+            file.write_all(&format::header()).await?;
+        }
+
         let serialized_op = bincode::serialize(op)?;
 
         let length = serialized_op.len() as u64;
@@ -41,36 +47,24 @@ impl WalManager {
     }
 
     pub async fn read_operations(&self) -> anyhow::Result<Vec<StorageOperation>> {
-        let data = match tokio::fs::read(&self.current_path).await {
-            Ok(data) => data,
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::NotFound {
-                    return Ok(Vec::new());
-                } else {
-                    return Err(err.into());
-                }
-            }
-        };
-
-        let mut operations = Vec::new();
-        let mut index = 0;
-
-        while index < data.len() {
-            let length = u64::from_le_bytes(data[index..index + 8].try_into().unwrap());
-            index += 8;
-
-            let end = index + length as usize;
-            let op_data = &data[index..end];
-            let op = bincode::deserialize(op_data)?;
-            operations.push(op);
+        Ok(self
+            .read_all_operations()
+            .await?
+            .into_iter()
+            .map(|(_, op)| op)
+            .collect())
+    }
 
-            index = end;
+    /// Whether the WAL was written in an older format, to be migrated.
+    pub async fn is_legacy(&self) -> anyhow::Result<bool> {
+        match tokio::fs::read(&self.current_path).await {
+            Ok(data) => Ok(!data.is_empty() && format::split_header(&data)?.0 < format::VERSION),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
         }
-
-        Ok(operations)
     }
 
-    pub async fn rotate(&mut self) -> anyhow::Result<()> {
+    pub async fn rotate(&self) -> anyhow::Result<()> {
         let timestamp = current_time();
 
         let archive_path = self.archive_dir.join(format!("wal-{}.log", timestamp));
@@ -142,6 +136,7 @@ impl WalManager {
             }
         };
 
+        let (version, data) = format::split_header(&data)?;
         let mut operations = Vec::new();
         let mut index = 0;
         let mut op_index = 0;
@@ -152,7 +147,7 @@ impl WalManager {
 
             let end = index + length as usize;
             let op_data = &data[index..end];
-            let op = bincode::deserialize(op_data)?;
+            let op = format::decode_operation(version, op_data)?;
             operations.push((op_index, op));
             op_index += 1;
 
@@ -174,6 +169,7 @@ impl WalManager {
                 .open(&temp_path)
                 .await?;
 
+            file.write_all(&format::header()).await?;
             for (_, op) in operations {
                 let serialized_op = bincode::serialize(&op)?;
                 let length = serialized_op.len() as u64;
@@ -196,6 +192,7 @@ impl WalManager {
             }
             StorageOperation::UpdateStatus { id, .. }
             | StorageOperation::UpdateState { id, .. }
+            | StorageOperation::UpdateHealth { id, .. }
             | StorageOperation::Delete(id) => {
                 if id.is_empty() {
                     return Err(anyhow::anyhow!("Container ID cannot be empty"));
@@ -495,6 +492,31 @@ mod tests {
         assert!(wal_manager.validate_operation(&batch_op).is_err());
     }
 
+    #[tokio::test]
+    async fn test_wal_legacy_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let wal_manager = WalManager::new(&temp_dir.path().to_path_buf(), 5)
+            .await
+            .unwrap();
+
+        // Written before the format was versioned, without a header.
+        let op = bincode::serialize(&StorageOperation::Delete("container1".to_string())).unwrap();
+        let mut data = (op.len() as u64).to_le_bytes().to_vec();
+        data.extend_from_slice(&op);
+        tokio::fs::write(&wal_manager.current_path, &data)
+            .await
+            .unwrap();
+
+        assert!(wal_manager.is_legacy().await.unwrap());
+        let operations = wal_manager.read_operations().await.unwrap();
+        assert!(matches!(&operations[..], [StorageOperation::Delete(id)] if id == "container1"));
+
+        wal_manager.rotate().await.unwrap();
+        wal_manager.write_operation(&operations[0]).await.unwrap();
+        assert!(!wal_manager.is_legacy().await.unwrap());
+        assert_eq!(wal_manager.read_operations().await.unwrap().len(), 1);
+    }
+
     #[tokio::test]
     async fn test_wal_empty_file() {
         let temp_dir = TempDir::new().unwrap();
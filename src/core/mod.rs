@@ -1,18 +1,25 @@
 use std::env;
 
+use image::{import_image, list_images, load_image, remove_images, save_image, tag_image, IMAGES};
 use log::{debug, error, info};
-use metas::{ContainerManager, CONTAINER_METAS};
-use network::{create_network, NETWORKS};
+use metas::{ContainerManager, LogEventHandler, CONTAINER_METAS};
+use network::{
+    connect_network, create_network, disconnect_network, inspect_network, list_networks,
+    remove_network, NETWORKS,
+};
 use tokio::{
     net::{UnixListener, UnixStream},
     task,
 };
+use volume::{create_volume, inspect_volume, list_volumes, remove_volumes, VOLUMES};
 
 mod cmd;
 mod container;
+mod image;
 mod metas;
 mod msg;
 mod network;
+mod volume;
 
 use container::*;
 
@@ -30,6 +37,9 @@ async fn run_daemon() -> tokio::io::Result<()> {
     let container_metas = ContainerManager::default()
         .await
         .expect("Fatal, failed to init container metas");
+    container_metas
+        .add_event_handler(Box::new(LogEventHandler))
+        .await;
     CONTAINER_METAS
         .set(container_metas)
         .expect("Fatal, failed to set container metas");
@@ -40,6 +50,20 @@ async fn run_daemon() -> tokio::io::Result<()> {
         .set(tokio::sync::Mutex::new(networks))
         .expect("Fatal, failed to set network metas");
 
+    let volumes = volume::Volumes::load().expect("Fatal, failed to init volume metas");
+    VOLUMES
+        .set(tokio::sync::Mutex::new(volumes))
+        .expect("Fatal, failed to set volume metas");
+
+    let images = image::Images::load(format!("{ROOT_PATH}/images/index"))
+        .expect("Fatal, failed to init image metas");
+    IMAGES
+        .set(tokio::sync::Mutex::new(images))
+        .expect("Fatal, failed to set image metas");
+
+    // Containers may have gone down with the previous daemon.
+    task::spawn(restore_containers());
+
     // Delete the old socket file
     if std::fs::exists(SOCKET_PATH).unwrap_or(false) {
         std::fs::remove_file(SOCKET_PATH)?;
@@ -83,12 +107,49 @@ async fn handler(mut stream: UnixStream) -> tokio::io::Result<()> {
         Commands::Start(start_args) => start_container(start_args, stream).await,
         Commands::Exec(exec_args) => exec_container(exec_args, stream).await,
         Commands::Stop(stop_args) => stop_container(stop_args, stream).await,
+        Commands::Pause(pause_args) => pause_container(pause_args, stream).await,
+        Commands::Unpause(unpause_args) => unpause_container(unpause_args, stream).await,
+        Commands::Kill(kill_args) => kill_container(kill_args, stream).await,
+        Commands::Inspect(inspect_args) => inspect_container(inspect_args, stream).await,
         Commands::RM(rm_args) => remove_container(rm_args, stream).await,
         Commands::PS(ps_args) => list_containers(ps_args, stream).await,
         Commands::Logs(logs_args) => show_logs(logs_args, stream).await,
         Commands::Commit(commit_args) => commit_container(commit_args, stream).await,
+        Commands::Images => list_images(stream).await,
+        Commands::Rmi(rmi_args) => remove_images(rmi_args, stream).await,
+        Commands::Tag(tag_args) => tag_image(tag_args, stream).await,
+        Commands::Import(import_args) => import_image(import_args, stream).await,
+        Commands::Update(update_args) => update_container(update_args, stream).await,
+        Commands::Container(container_commands) => match container_commands {
+            ContainerCommands::Prune(prune_args) => prune_containers(prune_args, stream).await,
+        },
+        Commands::Image(image_commands) => match image_commands {
+            ImageCommands::Load(load_args) => load_image(load_args, stream).await,
+            ImageCommands::Save(save_args) => save_image(save_args, stream).await,
+        },
         Commands::Network(network_commands) => match network_commands {
             NetworkCommands::Create(netcreate_args) => create_network(netcreate_args, stream).await,
+            NetworkCommands::Ls => list_networks(stream).await,
+            NetworkCommands::Inspect(name_args) => inspect_network(name_args, stream).await,
+            NetworkCommands::Rm(name_args) => remove_network(name_args, stream).await,
+            NetworkCommands::Connect(connect_args) => connect_network(connect_args, stream).await,
+            NetworkCommands::Disconnect(connect_args) => {
+                disconnect_network(connect_args, stream).await
+            }
+            NetworkCommands::Prune(prune_args) => prune_networks(prune_args, stream).await,
+        },
+        Commands::Volume(volume_commands) => match volume_commands {
+            VolumeCommands::Create(create_args) => create_volume(create_args, stream).await,
+            VolumeCommands::Ls => list_volumes(stream).await,
+            VolumeCommands::Inspect(name_args) => inspect_volume(name_args, stream).await,
+            VolumeCommands::Rm(rm_args) => remove_volumes(rm_args, stream).await,
+        },
+        Commands::Label(label_commands) => match label_commands {
+            LabelCommands::Add(add_args) => add_labels(add_args, stream).await,
+            LabelCommands::Rm(rm_args) => remove_labels(rm_args, stream).await,
+        },
+        Commands::System(system_commands) => match system_commands {
+            SystemCommands::Prune(prune_args) => prune_system(prune_args, stream).await,
         },
     };
 
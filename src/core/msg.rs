@@ -3,6 +3,8 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use super::CLI;
 
+// A message is built once per request, not worth boxing the request.
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Msg {
     /// Client Request
@@ -57,7 +59,10 @@ mod tests {
     #[test]
     fn test_msg_get_req() {
         let cli = CLI {
-            command: crate::core::Commands::PS(crate::core::PSArgs { all: false }),
+            command: crate::core::Commands::PS(crate::core::PSArgs {
+                all: false,
+                ..Default::default()
+            }),
         };
 
         let msg = Msg::Req(cli.clone());
@@ -73,7 +78,10 @@ mod tests {
     #[tokio::test]
     async fn test_msg_serialization() {
         let cli = CLI {
-            command: crate::core::Commands::PS(crate::core::PSArgs { all: false }),
+            command: crate::core::Commands::PS(crate::core::PSArgs {
+                all: false,
+                ..Default::default()
+            }),
         };
 
         // Test different message types
@@ -5,10 +5,16 @@ use std::{
 
 use anyhow::Context;
 use futures::TryStreamExt;
-use netlink_packet_route::link::LinkMessage;
+use netlink_packet_route::link::{LinkAttribute, LinkMessage};
+use nix::sched::{setns, CloneFlags};
+
+use crate::core::metas::current_time;
 
 use super::{network::Network, Endpoint};
 
+/// Name of the network interface inside containers.
+const CONTAINER_IFACE: &str = "eth0";
+
 pub struct BridgeDriver {}
 
 impl BridgeDriver {
@@ -46,10 +52,13 @@ impl BridgeDriver {
             cidr: cidr.to_string(),
             gateway: gateway,
             driver: "bridge".to_string(),
+            created_at: current_time(),
         })
     }
 
     pub async fn delete_network(&self, network: &Network) -> anyhow::Result<()> {
+        self.clear_basic_iptables(&network.name, &network.cidr)
+            .await?;
         self.delete_bridge(&network.name).await
     }
 
@@ -83,6 +92,81 @@ impl BridgeDriver {
         Ok(endpoint.container_ip)
     }
 
+    /// Configure the peer veth inside the container's network namespace, which is renamed to
+    /// `eth0`, given the endpoint address and routed through the bridge gateway. Returns the
+    /// MAC address of the container interface.
+    pub async fn setup_endpoint(
+        &self,
+        network: &Network,
+        endpoint: &Endpoint,
+    ) -> anyhow::Result<String> {
+        let prefix_len = network
+            .cidr
+            .split('/')
+            .nth(1)
+            .ok_or(anyhow::anyhow!("Invalid CIDR"))?
+            .parse::<u8>()?;
+
+        let netns_path = format!("/proc/{}/ns/net", endpoint.container_id);
+        let handle = self
+            .netns_handle(&netns_path)
+            .context("Failed to open netlink in container netns")?;
+
+        let lo = self.get_link_by_name("lo", &handle).await?;
+        handle.link().set(lo.header.index).up().execute().await?;
+
+        let peer = self.get_link_by_name(&endpoint.veth_peer, &handle).await?;
+        handle
+            .link()
+            .set(peer.header.index)
+            .name(CONTAINER_IFACE.to_string())
+            .execute()
+            .await
+            .context("Failed to rename container veth")?;
+        handle
+            .address()
+            .add(peer.header.index, endpoint.container_ip.into(), prefix_len)
+            .execute()
+            .await
+            .context("Failed to set container ip")?;
+        handle
+            .link()
+            .set(peer.header.index)
+            .up()
+            .execute()
+            .await
+            .context("Failed to set container veth up")?;
+        handle
+            .route()
+            .add()
+            .v4()
+            .gateway(network.gateway)
+            .execute()
+            .await
+            .context("Failed to set default route")?;
+
+        let mac = peer
+            .attributes
+            .iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::Address(addr) => Some(
+                    addr.iter()
+                        .map(|b| format!("{b:02x}"))
+                        .collect::<Vec<_>>()
+                        .join(":"),
+                ),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        Ok(mac)
+    }
+
+    /// Remove the endpoint from the host, deleting the host veth also deletes its peer.
+    pub async fn disconnect(&self, endpoint: &Endpoint) -> anyhow::Result<()> {
+        self.delete_link(&endpoint.veth_host).await
+    }
+
     async fn create_bridge(&self, name: &str) -> anyhow::Result<()> {
         let (connection, handle, _) = rtnetlink::new_connection()?;
         tokio::spawn(connection);
@@ -97,6 +181,10 @@ impl BridgeDriver {
     }
 
     async fn delete_bridge(&self, name: &str) -> anyhow::Result<()> {
+        self.delete_link(name).await
+    }
+
+    async fn delete_link(&self, name: &str) -> anyhow::Result<()> {
         let (connection, handle, _) = rtnetlink::new_connection()?;
         tokio::spawn(connection);
 
@@ -106,6 +194,23 @@ impl BridgeDriver {
         Ok(())
     }
 
+    /// Netlink sockets stay in the namespace they were created in, so switch this thread into
+    /// `netns` just for creating the connection.
+    fn netns_handle(&self, netns: &str) -> anyhow::Result<rtnetlink::Handle> {
+        let origin = std::fs::File::open("/proc/thread-self/ns/net")?;
+        let target = std::fs::File::open(netns)?;
+
+        setns(target.as_fd(), CloneFlags::CLONE_NEWNET)?;
+        let conn = rtnetlink::new_connection();
+        setns(origin.as_fd(), CloneFlags::CLONE_NEWNET)
+            .context("Failed to switch back to daemon netns")?;
+
+        let (connection, handle, _) = conn?;
+        tokio::spawn(connection);
+
+        Ok(handle)
+    }
+
     async fn create_veth_pair(&self, host_veth: &str, peer_veth: &str) -> anyhow::Result<()> {
         let (connection, handle, _) = rtnetlink::new_connection()?;
         tokio::spawn(connection);
@@ -183,33 +288,61 @@ impl BridgeDriver {
         Ok(())
     }
 
+    /// Forward tcp `host_port` on the host to `container_port` of the container at `ip`.
+    pub async fn publish_port(
+        &self,
+        ip: Ipv4Addr,
+        host_port: u16,
+        container_port: u16,
+    ) -> anyhow::Result<()> {
+        for rule in port_rules(ip, host_port, container_port) {
+            if let Err(e) = rule.exec("-A").await {
+                // Do not leave half of the rules behind.
+                let _ = self.unpublish_port(ip, host_port, container_port).await;
+                return Err(e).context("Failed to set port publishing rule");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the rules added by `publish_port`, rules already gone are skipped.
+    pub async fn unpublish_port(
+        &self,
+        ip: Ipv4Addr,
+        host_port: u16,
+        container_port: u16,
+    ) -> anyhow::Result<()> {
+        for rule in port_rules(ip, host_port, container_port) {
+            if rule.exec("-C").await.is_ok() {
+                rule.exec("-D")
+                    .await
+                    .context("Failed to delete port publishing rule")?;
+            }
+        }
+
+        Ok(())
+    }
+
     async fn set_basic_iptables(&self, name: &str, cidr: &str) -> anyhow::Result<()> {
-        async fn exec_iptables(args: &[&str]) -> anyhow::Result<()> {
-            tokio::process::Command::new("iptables")
-                .args(args)
-                .status()
-                .await?
-                .success()
-                .then_some(())
-                .context("`iptables` exited with non-zero status")
+        for rule in basic_rules(name, cidr) {
+            if let Err(e) = rule.exec("-A").await {
+                let _ = self.clear_basic_iptables(name, cidr).await;
+                return Err(e).context(format!("Failed to set {} rule", rule.chain));
+            }
         }
 
-        exec_iptables(&["-A", "FORWARD", "-i", &name, "-j", "ACCEPT"])
-            .await
-            .context("Failed to set FORWARD rule")?;
+        Ok(())
+    }
 
-        exec_iptables(&[
-            "-t",
-            "nat",
-            "-A",
-            "POSTROUTING",
-            "-s",
-            &cidr.to_string(),
-            "-j",
-            "MASQUERADE",
-        ])
-        .await
-        .context("Failed to set MASQUERADE rule")?;
+    async fn clear_basic_iptables(&self, name: &str, cidr: &str) -> anyhow::Result<()> {
+        for rule in basic_rules(name, cidr) {
+            if rule.exec("-C").await.is_ok() {
+                rule.exec("-D")
+                    .await
+                    .context(format!("Failed to delete {} rule", rule.chain))?;
+            }
+        }
 
         Ok(())
     }
@@ -229,3 +362,170 @@ impl BridgeDriver {
             .ok_or(anyhow::anyhow!("Link not found"))
     }
 }
+
+async fn exec_iptables(args: &[&str]) -> anyhow::Result<()> {
+    tokio::process::Command::new("iptables")
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await?
+        .success()
+        .then_some(())
+        .context("`iptables` exited with non-zero status")
+}
+
+/// An iptables rule, applied with an action like `-A`, `-C` or `-D`.
+#[derive(Debug, PartialEq)]
+struct IptablesRule {
+    table: &'static str,
+    chain: &'static str,
+    spec: Vec<String>,
+}
+
+impl IptablesRule {
+    fn new(table: &'static str, chain: &'static str, spec: &[&str]) -> Self {
+        Self {
+            table,
+            chain,
+            spec: spec.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn args<'a>(&'a self, action: &'a str) -> Vec<&'a str> {
+        let mut args = vec!["-t", self.table, action, self.chain];
+        args.extend(self.spec.iter().map(String::as_str));
+        args
+    }
+
+    async fn exec(&self, action: &str) -> anyhow::Result<()> {
+        exec_iptables(&self.args(action)).await
+    }
+}
+
+fn basic_rules(name: &str, cidr: &str) -> Vec<IptablesRule> {
+    vec![
+        IptablesRule::new("filter", "FORWARD", &["-i", name, "-j", "ACCEPT"]),
+        IptablesRule::new("nat", "POSTROUTING", &["-s", cidr, "-j", "MASQUERADE"]),
+    ]
+}
+
+fn port_rules(ip: Ipv4Addr, host_port: u16, container_port: u16) -> Vec<IptablesRule> {
+    let ip = ip.to_string();
+    let host_port = host_port.to_string();
+    let container_port = container_port.to_string();
+    let destination = format!("{ip}:{container_port}");
+
+    vec![
+        // DNAT for traffic coming from outside.
+        IptablesRule::new(
+            "nat",
+            "PREROUTING",
+            &[
+                "-p",
+                "tcp",
+                "-m",
+                "addrtype",
+                "--dst-type",
+                "LOCAL",
+                "--dport",
+                &host_port,
+                "-j",
+                "DNAT",
+                "--to-destination",
+                &destination,
+            ],
+        ),
+        // DNAT for traffic from the host itself.
+        IptablesRule::new(
+            "nat",
+            "OUTPUT",
+            &[
+                "-p",
+                "tcp",
+                "-m",
+                "addrtype",
+                "--dst-type",
+                "LOCAL",
+                "--dport",
+                &host_port,
+                "-j",
+                "DNAT",
+                "--to-destination",
+                &destination,
+            ],
+        ),
+        // Hairpin, the container reaching itself through the published port.
+        IptablesRule::new(
+            "nat",
+            "POSTROUTING",
+            &[
+                "-p",
+                "tcp",
+                "-s",
+                &ip,
+                "-d",
+                &ip,
+                "--dport",
+                &container_port,
+                "-j",
+                "MASQUERADE",
+            ],
+        ),
+        // Let the forwarded traffic into the bridge.
+        IptablesRule::new(
+            "filter",
+            "FORWARD",
+            &[
+                "-p",
+                "tcp",
+                "-d",
+                &ip,
+                "--dport",
+                &container_port,
+                "-j",
+                "ACCEPT",
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_rules() {
+        let rules = port_rules(Ipv4Addr::new(10, 0, 0, 2), 8080, 80);
+        assert_eq!(rules.len(), 4);
+
+        assert_eq!(
+            rules[0].args("-A"),
+            vec![
+                "-t",
+                "nat",
+                "-A",
+                "PREROUTING",
+                "-p",
+                "tcp",
+                "-m",
+                "addrtype",
+                "--dst-type",
+                "LOCAL",
+                "--dport",
+                "8080",
+                "-j",
+                "DNAT",
+                "--to-destination",
+                "10.0.0.2:80",
+            ]
+        );
+        assert_eq!(
+            rules[3].args("-D"),
+            vec![
+                "-t", "filter", "-D", "FORWARD", "-p", "tcp", "-d", "10.0.0.2", "--dport", "80",
+                "-j", "ACCEPT",
+            ]
+        );
+    }
+}
@@ -32,6 +32,13 @@ impl IPAM {
         Ok(())
     }
 
+    pub fn remove_subnet(&mut self, cidr: &str) -> anyhow::Result<()> {
+        self.subnets
+            .remove(cidr)
+            .map(|_| ())
+            .ok_or(anyhow::anyhow!("Subnet not found"))
+    }
+
     pub fn allocate_ip(&mut self, cidr: &str) -> anyhow::Result<Ipv4Addr> {
         let bitmap = self
             .subnets
@@ -172,6 +179,11 @@ mod tests {
 
         // Test adding duplicate subnet
         assert!(ipam.add_subnet("192.168.1.0/24").is_err());
+
+        // Test removing subnet, after which it can be added again
+        assert!(ipam.remove_subnet("192.168.1.0/24").is_ok());
+        assert!(ipam.remove_subnet("192.168.1.0/24").is_err());
+        assert!(ipam.add_subnet("192.168.1.0/24").is_ok());
     }
 
     #[test]
@@ -13,5 +13,19 @@ struct Endpoint {
     pub container_ip: Ipv4Addr,
 }
 
+impl Endpoint {
+    /// Veth names are derived from the container id, as interface names are limited to 15 bytes.
+    fn new(id: &str, pid: i32, container_ip: Ipv4Addr) -> Self {
+        let short_id = id.get(..8).unwrap_or(id);
+
+        Self {
+            container_id: pid.to_string(),
+            veth_host: format!("veth{short_id}"),
+            veth_peer: format!("ceth{short_id}"),
+            container_ip,
+        }
+    }
+}
+
 pub static NETWORKS: OnceCell<Mutex<Networks>> = OnceCell::const_new();
 pub use network::*;
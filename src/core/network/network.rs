@@ -1,16 +1,22 @@
 use std::{
-    collections::HashMap,
-    io::Read,
-    net::Ipv4Addr,
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpListener},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
 };
 
+use log::error;
 use serde::{Deserialize, Serialize};
-use tokio::net::UnixStream;
+use tabwriter::TabWriter;
+use tokio::{net::UnixStream, sync::Mutex};
 
-use crate::core::{Msg, NetCreateArgs};
+use crate::core::{
+    metas::{current_time, ContainerFilter, ContainerMeta, NetworkConfig, CONTAINER_METAS},
+    Msg, NetConnectArgs, NetCreateArgs, NetNameArgs,
+};
 
-use super::{bridge::BridgeDriver, ipam::IPAM, NETWORKS};
+use super::{bridge::BridgeDriver, ipam::IPAM, Endpoint, NETWORKS};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Network {
@@ -20,8 +26,14 @@ pub struct Network {
     #[serde(deserialize_with = "deserialize_ipv4")]
     pub gateway: Ipv4Addr,
     pub driver: String,
+    pub created_at: u64,
 }
 
+/// Start of the networks file, followed by the format version as a little-endian `u32`. Files
+/// without it are of version 0, before networks had a creation time.
+const MAGIC: &[u8; 8] = b"RTAINNW\0";
+const VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Networks {
     pub ipam: IPAM,
@@ -40,7 +52,7 @@ impl Networks {
             let mut contents = Vec::new();
             file.read_to_end(&mut contents)?;
 
-            let mut networks: Networks = bincode::deserialize(&contents)?;
+            let mut networks = Self::decode(&contents)?;
             networks.path = path;
 
             Ok(networks)
@@ -58,11 +70,83 @@ impl Networks {
     }
 
     pub fn save(&self) -> anyhow::Result<()> {
-        let contents = bincode::serialize(self)?;
-        std::fs::write(&self.path, contents)?;
+        let mut contents = [MAGIC.as_slice(), &VERSION.to_le_bytes()].concat();
+        contents.extend(bincode::serialize(self)?);
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &self.path)?;
 
         Ok(())
     }
+
+    fn decode(contents: &[u8]) -> anyhow::Result<Self> {
+        let Some(rest) = contents.strip_prefix(MAGIC.as_slice()) else {
+            return Ok(bincode::deserialize::<v0::Networks>(contents)?.into());
+        };
+
+        let (version, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(anyhow::anyhow!("Truncated networks header"))?;
+        match u32::from_le_bytes(*version) {
+            VERSION => Ok(bincode::deserialize(rest)?),
+            version => Err(anyhow::anyhow!(
+                "Networks of format version {} are newer than supported, up to {}",
+                version,
+                VERSION
+            )),
+        }
+    }
+}
+
+/// Networks before they had a creation time.
+mod v0 {
+    use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf};
+
+    use serde::Deserialize;
+
+    use super::{current_time, deserialize_ipv4, IPAM};
+
+    #[derive(Deserialize)]
+    pub struct Network {
+        name: String,
+        cidr: String,
+        #[serde(deserialize_with = "deserialize_ipv4")]
+        gateway: Ipv4Addr,
+        driver: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Networks {
+        ipam: IPAM,
+        networks: HashMap<String, Network>,
+        path: PathBuf,
+    }
+
+    impl From<Networks> for super::Networks {
+        fn from(networks: Networks) -> Self {
+            // The creation time is unknown, take them as created now rather than long ago.
+            let created_at = current_time();
+
+            Self {
+                ipam: networks.ipam,
+                networks: networks
+                    .networks
+                    .into_iter()
+                    .map(|(name, network)| {
+                        let network = super::Network {
+                            name: network.name,
+                            cidr: network.cidr,
+                            gateway: network.gateway,
+                            driver: network.driver,
+                            created_at,
+                        };
+                        (name, network)
+                    })
+                    .collect(),
+                path: networks.path,
+            }
+        }
+    }
 }
 
 fn serialize_ipv4<S>(ip: &Ipv4Addr, serializer: S) -> Result<S::Ok, S::Error>
@@ -175,3 +259,648 @@ pub async fn create_network(create_args: NetCreateArgs, mut stream: UnixStream)
     networks_locked.networks.insert(create_args.name, network);
     let _ = networks_locked.save();
 }
+
+pub async fn list_networks(mut stream: UnixStream) {
+    let networks_locked = NETWORKS.get().unwrap().lock().await;
+
+    let mut names: Vec<_> = networks_locked.networks.keys().collect();
+    names.sort();
+
+    let mut tw = TabWriter::new(vec![]);
+    let _ = tw.write_all(b"NAME\tDRIVER\tSUBNET\tGATEWAY\tCONTAINERS\n");
+
+    for name in names {
+        let network = &networks_locked.networks[name];
+        let _ = writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}",
+            network.name,
+            network.driver,
+            network.cidr,
+            network.gateway,
+            attached_containers(name).await.len()
+        );
+    }
+
+    match tw.into_inner() {
+        Ok(data) => {
+            let _ = Msg::OkContent(String::from_utf8(data).unwrap())
+                .send_to(&mut stream)
+                .await;
+        }
+        Err(e) => {
+            error!("Failed to write to tab writer: {}", e);
+
+            let _ = Msg::Err(format!("Failed to write to tab writer: {}", e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+pub async fn inspect_network(args: NetNameArgs, mut stream: UnixStream) {
+    let networks_locked = NETWORKS.get().unwrap().lock().await;
+
+    let network = match networks_locked.networks.get(&args.name) {
+        Some(network) => network,
+        None => {
+            error!("Failed to inspect network {}, does not exist", args.name);
+            let _ = Msg::Err(format!(
+                "Failed to inspect network {}, does not exist",
+                args.name
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    let mut tw = TabWriter::new(vec![]);
+    let _ = writeln!(tw, "Name:\t{}", network.name);
+    let _ = writeln!(tw, "Driver:\t{}", network.driver);
+    let _ = writeln!(tw, "Subnet:\t{}", network.cidr);
+    let _ = writeln!(tw, "Gateway:\t{}", network.gateway);
+    let _ = writeln!(tw, "Containers:");
+    for meta in attached_containers(&network.name).await {
+        let Some(config) = meta.network else {
+            continue;
+        };
+        let mut ports: Vec<_> = config
+            .ports
+            .iter()
+            .map(|(host, container)| format!("{host}->{container}"))
+            .collect();
+        ports.sort();
+
+        let _ = writeln!(
+            tw,
+            "  {}\t{}\t{}\t{}",
+            meta.name,
+            config.ip_address.unwrap_or_default(),
+            config.mac_address.unwrap_or_default(),
+            ports.join(",")
+        );
+    }
+
+    match tw.into_inner() {
+        Ok(data) => {
+            let _ = Msg::OkContent(String::from_utf8(data).unwrap())
+                .send_to(&mut stream)
+                .await;
+        }
+        Err(e) => {
+            error!("Failed to write to tab writer: {}", e);
+
+            let _ = Msg::Err(format!("Failed to write to tab writer: {}", e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+pub async fn remove_network(args: NetNameArgs, mut stream: UnixStream) {
+    let mut networks_locked = NETWORKS.get().unwrap().lock().await;
+
+    let network = match networks_locked.networks.get(&args.name) {
+        Some(network) => network,
+        None => {
+            error!("Failed to rm network {}, does not exist", args.name);
+            let _ = Msg::Err(format!(
+                "Failed to rm network {}, does not exist",
+                args.name
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    let attached = attached_containers(&args.name).await;
+    if !attached.is_empty() {
+        let names: Vec<_> = attached.iter().map(|meta| meta.name.as_str()).collect();
+        error!(
+            "Failed to rm network {}, containers still attached: {}",
+            args.name,
+            names.join(", ")
+        );
+        let _ = Msg::Err(format!(
+            "Failed to rm network {}, containers still attached: {}",
+            args.name,
+            names.join(", ")
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    if let Err(e) = BRIDGEDRIVER.delete_network(network).await {
+        error!("Failed to rm network {}, driver error: {e}", args.name);
+        let _ = Msg::Err(format!(
+            "Failed to rm network {}, driver error: {e}",
+            args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+    forget_network(&mut networks_locked, &args.name);
+
+    let _ = Msg::OkContent(format!("Network {} removed", args.name))
+        .send_to(&mut stream)
+        .await;
+}
+
+/// Remove the networks matching `filter` without attached containers. Networks carry no labels,
+/// so none matches a label filter. Returns the names removed and the failures.
+pub async fn remove_unused_networks(filter: &ContainerFilter) -> (Vec<String>, Vec<String>) {
+    if !filter.labels.is_empty() {
+        return (vec![], vec![]);
+    }
+
+    let mut networks_locked = NETWORKS.get().unwrap().lock().await;
+
+    let mut names: Vec<_> = networks_locked
+        .networks
+        .values()
+        .filter(|network| filter.until.is_none_or(|until| network.created_at <= until))
+        .map(|network| network.name.clone())
+        .collect();
+    names.sort();
+
+    let mut removed = vec![];
+    let mut errors = vec![];
+    for name in names {
+        if !attached_containers(&name).await.is_empty() {
+            continue;
+        }
+
+        let network = &networks_locked.networks[&name];
+        if let Err(e) = BRIDGEDRIVER.delete_network(network).await {
+            error!("Failed to rm network {}, driver error: {e}", name);
+            errors.push(format!("Failed to rm network {name}, driver error: {e}"));
+            continue;
+        }
+        forget_network(&mut networks_locked, &name);
+
+        removed.push(name);
+    }
+
+    (removed, errors)
+}
+
+/// Drop the record of a deleted network and give its subnet back.
+fn forget_network(networks: &mut Networks, name: &str) {
+    // The gateway is the only address left in the subnet.
+    let network = networks.networks.remove(name).unwrap();
+    let _ = networks.ipam.release_ip(&network.cidr, network.gateway);
+    let _ = networks.ipam.remove_subnet(&network.cidr);
+
+    if let Err(e) = networks.save() {
+        error!("Failed to save networks: {e}");
+    }
+}
+
+pub async fn connect_network(args: NetConnectArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&args.container).await {
+        Some(meta) => meta,
+        None => {
+            error!(
+                "Failed to connect container {}, record does not exist",
+                args.container
+            );
+            let _ = Msg::Err(format!(
+                "Failed to connect container {}, record does not exist",
+                args.container
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if let Some(network) = &meta.network {
+        error!(
+            "Failed to connect container {}, already connected to network {}",
+            args.container, network.network_name
+        );
+        let _ = Msg::Err(format!(
+            "Failed to connect container {}, already connected to network {}",
+            args.container, network.network_name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    }
+
+    // A running container is connected right away, a stopped one gets its endpoint on start.
+    let res = match meta.get_pid() {
+        Some(pid) if meta.state.status.can_stop() => {
+            connect_container(&args.network, &meta.id, pid, None).await
+        }
+        _ => reserve_address(&args.network).await,
+    };
+    let network = match res {
+        Ok(network) => network,
+        Err(e) => {
+            error!(
+                "Failed to connect container {} to network {}: {:?}",
+                args.container, args.network, e
+            );
+            let _ = Msg::Err(format!(
+                "Failed to connect container {} to network {}: {}",
+                args.container, args.network, e
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if let Err(e) = container_metas
+        .attach_network(meta.id.clone(), network.clone())
+        .await
+    {
+        if let Some(pid) = meta.get_pid() {
+            let _ = disconnect_container(&meta.id, pid, &network).await;
+        }
+        let _ = release_container(&network).await;
+
+        error!("Failed to attach network: {:?}", e);
+        let _ = Msg::Err(format!("Failed to attach network: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    let _ = Msg::OkContent(format!(
+        "Container {} connected to network {}",
+        args.container, args.network
+    ))
+    .send_to(&mut stream)
+    .await;
+}
+
+pub async fn disconnect_network(args: NetConnectArgs, mut stream: UnixStream) {
+    let container_metas = CONTAINER_METAS.get().unwrap();
+
+    let meta = match container_metas.get_meta_by_name(&args.container).await {
+        Some(meta) => meta,
+        None => {
+            error!(
+                "Failed to disconnect container {}, record does not exist",
+                args.container
+            );
+            let _ = Msg::Err(format!(
+                "Failed to disconnect container {}, record does not exist",
+                args.container
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    let network = match meta.network.clone() {
+        Some(network) if network.network_name == args.network => network,
+        _ => {
+            error!(
+                "Failed to disconnect container {}, not connected to network {}",
+                args.container, args.network
+            );
+            let _ = Msg::Err(format!(
+                "Failed to disconnect container {}, not connected to network {}",
+                args.container, args.network
+            ))
+            .send_to(&mut stream)
+            .await;
+
+            return;
+        }
+    };
+
+    if let Err(e) = unpublish_ports(&meta.id, &network).await {
+        error!("Failed to unpublish ports: {:?}", e);
+    }
+    if let Some(pid) = meta.get_pid() {
+        if meta.state.status.can_stop() {
+            if let Err(e) = disconnect_container(&meta.id, pid, &network).await {
+                error!("Failed to remove container endpoint: {:?}", e);
+            }
+        }
+    }
+    if let Err(e) = release_container(&network).await {
+        error!("Failed to release container address: {:?}", e);
+    }
+
+    if let Err(e) = container_metas.detach_network(meta.id.clone()).await {
+        error!("Failed to detach network: {:?}", e);
+        let _ = Msg::Err(format!("Failed to detach network: {}", e))
+            .send_to(&mut stream)
+            .await;
+
+        return;
+    }
+
+    let _ = Msg::OkContent(format!(
+        "Container {} disconnected from network {}",
+        args.container, args.network
+    ))
+    .send_to(&mut stream)
+    .await;
+}
+
+/// Containers whose records say they are attached to network `net_name`.
+async fn attached_containers(net_name: &str) -> Vec<ContainerMeta> {
+    CONTAINER_METAS
+        .get()
+        .unwrap()
+        .get_all_metas()
+        .await
+        .into_iter()
+        .filter(|meta| {
+            meta.network
+                .as_ref()
+                .is_some_and(|network| network.network_name == net_name)
+        })
+        .collect()
+}
+
+/// Allocate an address on network `net_name` for a container that is not running.
+async fn reserve_address(net_name: &str) -> anyhow::Result<NetworkConfig> {
+    let networks = NETWORKS
+        .get()
+        .ok_or(anyhow::anyhow!("Networks not initialized"))?;
+    let mut networks_locked = networks.lock().await;
+
+    let cidr = match networks_locked.networks.get(net_name) {
+        Some(network) => network.cidr.clone(),
+        None => return Err(anyhow::anyhow!("Network {} does not exist", net_name)),
+    };
+
+    let ip = networks_locked.ipam.allocate_ip(&cidr)?;
+    networks_locked.save()?;
+
+    Ok(NetworkConfig {
+        ip_address: Some(ip.to_string()),
+        network_name: net_name.to_string(),
+        mac_address: None,
+        ports: HashMap::new(),
+    })
+}
+
+/// Remove the endpoint of the running container process `pid`.
+async fn disconnect_container(id: &str, pid: i32, config: &NetworkConfig) -> anyhow::Result<()> {
+    let ip = match &config.ip_address {
+        Some(ip) => ip.parse()?,
+        None => return Ok(()),
+    };
+
+    BRIDGEDRIVER.disconnect(&Endpoint::new(id, pid, ip)).await
+}
+
+/// Connect the container process `pid` to network `net_name`. A container keeps its address
+/// across restarts, so `ip` is reused if given, otherwise a new one is allocated.
+pub async fn connect_container(
+    net_name: &str,
+    id: &str,
+    pid: i32,
+    ip: Option<Ipv4Addr>,
+) -> anyhow::Result<NetworkConfig> {
+    let networks = NETWORKS
+        .get()
+        .ok_or(anyhow::anyhow!("Networks not initialized"))?;
+    let mut networks_locked = networks.lock().await;
+
+    let cidr = match networks_locked.networks.get(net_name) {
+        Some(network) => network.cidr.clone(),
+        None => return Err(anyhow::anyhow!("Network {} does not exist", net_name)),
+    };
+
+    let (ip, allocated) = match ip {
+        Some(ip) => (ip, false),
+        None => (networks_locked.ipam.allocate_ip(&cidr)?, true),
+    };
+
+    let endpoint = Endpoint::new(id, pid, ip);
+    let network = &networks_locked.networks[net_name];
+    let res = async {
+        BRIDGEDRIVER.connect(network, &endpoint).await?;
+        BRIDGEDRIVER.setup_endpoint(network, &endpoint).await
+    }
+    .await;
+
+    let mac = match res {
+        Ok(mac) => mac,
+        Err(e) => {
+            let _ = BRIDGEDRIVER.disconnect(&endpoint).await;
+            if allocated {
+                let _ = networks_locked.ipam.release_ip(&cidr, ip);
+            }
+
+            return Err(e);
+        }
+    };
+
+    if allocated {
+        networks_locked.save()?;
+    }
+
+    Ok(NetworkConfig {
+        ip_address: Some(ip.to_string()),
+        network_name: net_name.to_string(),
+        mac_address: Some(mac),
+        ports: HashMap::new(),
+    })
+}
+
+/// Give the address of a removed container back to its network.
+pub async fn release_container(config: &NetworkConfig) -> anyhow::Result<()> {
+    let networks = NETWORKS
+        .get()
+        .ok_or(anyhow::anyhow!("Networks not initialized"))?;
+    let mut networks_locked = networks.lock().await;
+
+    let cidr = match networks_locked.networks.get(&config.network_name) {
+        Some(network) => network.cidr.clone(),
+        None => {
+            return Err(anyhow::anyhow!(
+                "Network {} does not exist",
+                config.network_name
+            ))
+        }
+    };
+
+    if let Some(ip) = &config.ip_address {
+        networks_locked.ipam.release_ip(&cidr, ip.parse()?)?;
+        networks_locked.save()?;
+    }
+
+    Ok(())
+}
+
+/// Host ports to publish exposed ports on, as taken by Linux for ephemeral ports.
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 32768..=60999;
+
+/// Host ports published by containers of this daemon, to the container IDs. Locked from checking
+/// a port is free until it is recorded here, so that two containers cannot both take it.
+static PUBLISHED_PORTS: Mutex<BTreeMap<u16, String>> = Mutex::const_new(BTreeMap::new());
+
+/// Name of the container other than `id` publishing host `port`, if any. Containers that outlived
+/// an earlier daemon are only known from their records.
+async fn port_owner(published: &BTreeMap<u16, String>, id: &str, port: u16) -> Option<String> {
+    let container_metas = CONTAINER_METAS.get()?;
+
+    if let Some(owner) = published.get(&port).filter(|owner| *owner != id) {
+        return Some(
+            container_metas
+                .get_meta_by_id(owner)
+                .await
+                .map_or(owner.clone(), |meta| meta.name),
+        );
+    }
+
+    container_metas
+        .get_all_metas()
+        .await
+        .into_iter()
+        .find(|meta| {
+            meta.id != id
+                && meta.state.status.can_stop()
+                && meta
+                    .network
+                    .as_ref()
+                    .is_some_and(|network| network.ports.contains_key(&port))
+        })
+        .map(|meta| meta.name)
+}
+
+/// Install the port publishing rules recorded in `config` for container `id`, unless another
+/// running container publishes one of the host ports. Each port in `exposed` is published as well,
+/// on a free host port recorded in `config`.
+pub async fn publish_ports(
+    id: &str,
+    config: &mut NetworkConfig,
+    exposed: &[u16],
+) -> anyhow::Result<()> {
+    let ip: Ipv4Addr = match &config.ip_address {
+        Some(ip) => ip.parse()?,
+        None => return Err(anyhow::anyhow!("Container has no address to publish to")),
+    };
+
+    let mut published = PUBLISHED_PORTS.lock().await;
+    for &host_port in config.ports.keys() {
+        if let Some(owner) = port_owner(&published, id, host_port).await {
+            return Err(anyhow::anyhow!(
+                "Host port {} is already published by container {}",
+                host_port,
+                owner
+            ));
+        }
+    }
+
+    let mut candidates = EPHEMERAL_PORTS;
+    for &container_port in exposed {
+        let mut host_port = None;
+        for port in candidates.by_ref() {
+            if !config.ports.contains_key(&port)
+                && TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).is_ok()
+                && port_owner(&published, id, port).await.is_none()
+            {
+                host_port = Some(port);
+                break;
+            }
+        }
+
+        let host_port = host_port.ok_or(anyhow::anyhow!(
+            "No free host port left to publish port {}",
+            container_port
+        ))?;
+        config.ports.insert(host_port, container_port);
+    }
+
+    for (&host_port, &container_port) in &config.ports {
+        if let Err(e) = BRIDGEDRIVER
+            .publish_port(ip, host_port, container_port)
+            .await
+        {
+            let _ = remove_port_rules(ip, config).await;
+            return Err(e);
+        }
+    }
+    for &host_port in config.ports.keys() {
+        published.insert(host_port, id.to_string());
+    }
+
+    Ok(())
+}
+
+/// Remove the port publishing rules recorded in `config` for container `id`, freeing the ports.
+pub async fn unpublish_ports(id: &str, config: &NetworkConfig) -> anyhow::Result<()> {
+    let ip: Ipv4Addr = match &config.ip_address {
+        Some(ip) => ip.parse()?,
+        None => return Ok(()),
+    };
+
+    let mut published = PUBLISHED_PORTS.lock().await;
+    let removed = remove_port_rules(ip, config).await;
+    published.retain(|port, owner| owner != id || !config.ports.contains_key(port));
+
+    removed
+}
+
+async fn remove_port_rules(ip: Ipv4Addr, config: &NetworkConfig) -> anyhow::Result<()> {
+    for (&host_port, &container_port) in &config.ports {
+        BRIDGEDRIVER
+            .unpublish_port(ip, host_port, container_port)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_networks() {
+        // Networks as bincode wrote them in version 0, without a creation time.
+        let network = (
+            "net0".to_string(),
+            "10.0.0.0/24".to_string(),
+            Ipv4Addr::new(10, 0, 0, 1).to_bits(),
+            "bridge".to_string(),
+        );
+        let legacy = bincode::serialize(&(
+            IPAM::empty(),
+            HashMap::from([("net0".to_string(), network)]),
+            PathBuf::from("/tmp/rtain/net/networks"),
+        ))
+        .unwrap();
+
+        let networks = Networks::decode(&legacy).unwrap();
+        let network = &networks.networks["net0"];
+        assert_eq!(network.gateway, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(network.driver, "bridge");
+        assert!(network.created_at > 0);
+
+        let mut contents = [MAGIC.as_slice(), &VERSION.to_le_bytes()].concat();
+        contents.extend(bincode::serialize(&networks).unwrap());
+        let networks = Networks::decode(&contents).unwrap();
+        assert_eq!(networks.networks["net0"].cidr, "10.0.0.0/24");
+
+        let newer = [MAGIC.as_slice(), &(VERSION + 1).to_le_bytes()].concat();
+        assert!(Networks::decode(&newer).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tabwriter::TabWriter;
+use tokio::{
+    net::UnixStream,
+    sync::{Mutex, OnceCell},
+};
+
+use crate::core::{
+    container::random_id,
+    metas::{current_time, MountPoint, MountType},
+    Msg, VolumeCreateArgs, VolumeNameArgs, VolumeRmArgs, ROOT_PATH,
+};
+
+pub static VOLUMES: OnceCell<Mutex<Volumes>> = OnceCell::const_new();
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Volume {
+    pub name: String,
+    pub driver: String,
+    pub labels: HashMap<String, String>,
+    pub created_at: u64,
+    /// Containers the volume is mounted in, running or not.
+    pub ref_count: u32,
+    /// Created for a single container, and removed along with it by `rm -v`.
+    pub anonymous: bool,
+}
+
+/// Volumes by name, each kept under `ROOT_PATH/volumes/<name>`, with its record in `meta` and
+/// its data in `_data`.
+#[derive(Debug, Default)]
+pub struct Volumes {
+    volumes: HashMap<String, Volume>,
+}
+
+impl Volumes {
+    pub fn load() -> anyhow::Result<Self> {
+        let root = volume_dir("");
+        std::fs::create_dir_all(&root)?;
+
+        let mut volumes = HashMap::new();
+        for entry in std::fs::read_dir(&root)? {
+            // Directories without a record are not volumes.
+            let meta_path = entry?.path().join("meta");
+            if !meta_path.exists() {
+                continue;
+            }
+
+            let volume: Volume = bincode::deserialize(&std::fs::read(&meta_path)?)?;
+            volumes.insert(volume.name.clone(), volume);
+        }
+
+        Ok(Self { volumes })
+    }
+
+    /// Save the record of `volume` to a temporary file renamed into place, so that it is never
+    /// left half written.
+    fn save(&self, volume: &Volume) -> anyhow::Result<()> {
+        let contents = bincode::serialize(volume)?;
+        let dir = volume_dir(&volume.name);
+        let tmp_path = dir.join("meta.tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, dir.join("meta"))?;
+
+        Ok(())
+    }
+
+    fn create(
+        &mut self,
+        name: &str,
+        driver: &str,
+        labels: HashMap<String, String>,
+        anonymous: bool,
+    ) -> anyhow::Result<&mut Volume> {
+        if self.volumes.contains_key(name) {
+            return Err(anyhow::anyhow!("Volume {} already exists", name));
+        }
+        // Currently only local supported.
+        if driver != "local" {
+            return Err(anyhow::anyhow!("Invalid driver: {}", driver));
+        }
+
+        std::fs::create_dir_all(volume_path(name))?;
+
+        let volume = Volume {
+            name: name.to_string(),
+            driver: driver.to_string(),
+            labels,
+            created_at: current_time(),
+            ref_count: 0,
+            anonymous,
+        };
+        if let Err(e) = self.save(&volume) {
+            let _ = std::fs::remove_dir_all(volume_dir(name));
+            return Err(e);
+        }
+
+        Ok(self.volumes.entry(name.to_string()).or_insert(volume))
+    }
+
+    fn remove(&mut self, name: &str) -> anyhow::Result<()> {
+        let volume = self
+            .volumes
+            .get(name)
+            .ok_or(anyhow::anyhow!("Volume {} does not exist", name))?;
+        if volume.ref_count > 0 {
+            return Err(anyhow::anyhow!(
+                "Volume {} is in use by {} containers",
+                name,
+                volume.ref_count
+            ));
+        }
+
+        std::fs::remove_dir_all(volume_dir(name))?;
+        self.volumes.remove(name);
+
+        Ok(())
+    }
+}
+
+fn volume_dir(name: &str) -> PathBuf {
+    Path::new(ROOT_PATH).join("volumes").join(name)
+}
+
+/// Host directory holding the data of volume `name`.
+pub fn volume_path(name: &str) -> PathBuf {
+    volume_dir(name).join("_data")
+}
+
+pub async fn create_volume(create_args: VolumeCreateArgs, mut stream: UnixStream) {
+    let mut volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    let name = create_args.name.unwrap_or_else(random_id);
+    let labels = create_args.labels.into_iter().collect();
+
+    match volumes_locked.create(&name, &create_args.driver, labels, false) {
+        Ok(_) => {
+            info!("[Daemon] Volume {} created", name);
+            let _ = Msg::OkContent(name).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!("Failed to create volume {}: {}", name, e);
+            let _ = Msg::Err(format!("Failed to create volume {}: {}", name, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+pub async fn list_volumes(mut stream: UnixStream) {
+    let volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    let mut volumes: Vec<_> = volumes_locked.volumes.values().collect();
+    volumes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut tw = TabWriter::new(vec![]);
+    let _ = tw.write_all(b"NAME\tDRIVER\tCONTAINERS\n");
+    for volume in volumes {
+        let _ = writeln!(
+            tw,
+            "{}\t{}\t{}",
+            volume.name, volume.driver, volume.ref_count
+        );
+    }
+
+    match tw.into_inner() {
+        Ok(data) => {
+            let _ = Msg::OkContent(String::from_utf8(data).unwrap())
+                .send_to(&mut stream)
+                .await;
+        }
+        Err(e) => {
+            error!("Failed to write to tab writer: {}", e);
+
+            let _ = Msg::Err(format!("Failed to write to tab writer: {}", e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+pub async fn inspect_volume(args: VolumeNameArgs, mut stream: UnixStream) {
+    let volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    let Some(volume) = volumes_locked.volumes.get(&args.name) else {
+        error!("Failed to inspect volume {}, does not exist", args.name);
+        let _ = Msg::Err(format!(
+            "Failed to inspect volume {}, does not exist",
+            args.name
+        ))
+        .send_to(&mut stream)
+        .await;
+
+        return;
+    };
+
+    let output = serde_json::to_value(volume).and_then(|mut record| {
+        record["mountpoint"] = volume_path(&volume.name).to_string_lossy().into();
+        serde_json::to_string_pretty(&record)
+    });
+
+    match output {
+        Ok(output) => {
+            let _ = Msg::OkContent(output).send_to(&mut stream).await;
+        }
+        Err(e) => {
+            error!("Failed to inspect volume {}: {}", args.name, e);
+            let _ = Msg::Err(format!("Failed to inspect volume {}: {}", args.name, e))
+                .send_to(&mut stream)
+                .await;
+        }
+    }
+}
+
+/// Remove volumes, refusing those still used by a container.
+pub async fn remove_volumes(args: VolumeRmArgs, mut stream: UnixStream) {
+    let mut volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    let mut failed = false;
+    let mut lines = vec![];
+    for name in &args.names {
+        match volumes_locked.remove(name) {
+            Ok(()) => {
+                info!("[Daemon] Volume {} removed", name);
+                lines.push(format!("Volume {name} removed"));
+            }
+            Err(e) => {
+                error!("Failed to rm volume {}: {}", name, e);
+                lines.push(format!("Failed to rm volume {name}: {e}"));
+                failed = true;
+            }
+        }
+    }
+
+    let msg = if failed {
+        Msg::Err(lines.join("\n"))
+    } else {
+        Msg::OkContent(lines.join("\n"))
+    };
+    let _ = msg.send_to(&mut stream).await;
+}
+
+/// Take the volumes of a new container, creating those missing, and name its anonymous volumes.
+/// On failure, the volumes taken are given back.
+pub async fn acquire_volumes(mounts: &mut [MountPoint]) -> anyhow::Result<()> {
+    let mut volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    let mut failed = None;
+    for (i, mount) in mounts.iter_mut().enumerate() {
+        if mount.mount_type != MountType::Volume {
+            continue;
+        }
+
+        if let Err(e) = acquire_volume(&mut volumes_locked, mount) {
+            failed = Some((i, e));
+            break;
+        }
+    }
+
+    match failed {
+        Some((i, e)) => {
+            release_locked(&mut volumes_locked, &mounts[..i], true);
+            Err(e)
+        }
+        None => Ok(()),
+    }
+}
+
+fn acquire_volume(volumes: &mut Volumes, mount: &mut MountPoint) -> anyhow::Result<()> {
+    let volume = if mount.source.is_empty() {
+        mount.source = random_id();
+        volumes.create(&mount.source, "local", HashMap::new(), true)?
+    } else if volumes.volumes.contains_key(&mount.source) {
+        volumes.volumes.get_mut(&mount.source).unwrap()
+    } else {
+        volumes.create(&mount.source, "local", HashMap::new(), false)?
+    };
+    volume.ref_count += 1;
+
+    let volume = volume.clone();
+    volumes.save(&volume)
+}
+
+/// Give back the volumes of a removed container, removing the anonymous ones if `anonymous`.
+/// Returns the failures.
+pub async fn release_volumes(mounts: &[MountPoint], anonymous: bool) -> Vec<String> {
+    let mut volumes_locked = VOLUMES.get().unwrap().lock().await;
+
+    release_locked(&mut volumes_locked, mounts, anonymous)
+}
+
+fn release_locked(volumes: &mut Volumes, mounts: &[MountPoint], anonymous: bool) -> Vec<String> {
+    let mut errors = vec![];
+
+    for mount in mounts {
+        if mount.mount_type != MountType::Volume {
+            continue;
+        }
+        let Some(volume) = volumes.volumes.get_mut(&mount.source) else {
+            continue;
+        };
+
+        volume.ref_count = volume.ref_count.saturating_sub(1);
+        let volume = volume.clone();
+        if let Err(e) = volumes.save(&volume) {
+            errors.push(format!("cannot save volume {}: {}", volume.name, e));
+        }
+
+        if anonymous && volume.anonymous && volume.ref_count == 0 {
+            if let Err(e) = volumes.remove(&volume.name) {
+                errors.push(format!("cannot remove volume {}: {}", volume.name, e));
+            }
+        }
+    }
+
+    errors
+}
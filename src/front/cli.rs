@@ -25,14 +25,73 @@ async fn run_client() -> tokio::io::Result<()> {
         Commands::Start(start_args) => client_start_container(start_args, stream).await,
         Commands::Exec(exec_args) => client_exec_container(exec_args, stream).await,
         Commands::Stop(stop_args) => client_stop_container(stop_args, stream).await,
+        Commands::Pause(pause_args) => client_pause_container(pause_args, stream).await,
+        Commands::Unpause(unpause_args) => client_unpause_container(unpause_args, stream).await,
+        Commands::Kill(kill_args) => client_kill_container(kill_args, stream).await,
+        Commands::Inspect(inspect_args) => client_inspect_container(inspect_args, stream).await,
         Commands::RM(rm_args) => client_remove_container(rm_args, stream).await,
         Commands::PS(ps_args) => client_list_containers(ps_args, stream).await,
         Commands::Logs(logs_args) => client_show_logs(logs_args, stream).await,
         Commands::Commit(commit_args) => client_commit_container(commit_args, stream).await,
+        Commands::Images => client_list_images(stream).await,
+        Commands::Rmi(rmi_args) => client_remove_images(rmi_args, stream).await,
+        Commands::Tag(tag_args) => client_tag_image(tag_args, stream).await,
+        Commands::Import(import_args) => client_import_image(import_args, stream).await,
+        Commands::Update(update_args) => client_update_container(update_args, stream).await,
+        Commands::Container(container_commands) => match container_commands {
+            crate::core::ContainerCommands::Prune(prune_args) => {
+                client_prune_containers(prune_args, stream).await
+            }
+        },
+        Commands::Image(image_commands) => match image_commands {
+            crate::core::ImageCommands::Load(load_args) => {
+                client_load_image(load_args, stream).await
+            }
+            crate::core::ImageCommands::Save(save_args) => {
+                client_save_image(save_args, stream).await
+            }
+        },
         Commands::Network(network_commands) => match network_commands {
             crate::core::NetworkCommands::Create(netcreate_args) => {
                 client_create_network(netcreate_args, stream).await
             }
+            crate::core::NetworkCommands::Ls => client_list_networks(stream).await,
+            crate::core::NetworkCommands::Inspect(name_args) => {
+                client_inspect_network(name_args, stream).await
+            }
+            crate::core::NetworkCommands::Rm(name_args) => {
+                client_remove_network(name_args, stream).await
+            }
+            crate::core::NetworkCommands::Connect(connect_args) => {
+                client_connect_network(connect_args, stream).await
+            }
+            crate::core::NetworkCommands::Disconnect(connect_args) => {
+                client_disconnect_network(connect_args, stream).await
+            }
+            crate::core::NetworkCommands::Prune(prune_args) => {
+                client_prune_networks(prune_args, stream).await
+            }
+        },
+        Commands::Volume(volume_commands) => match volume_commands {
+            crate::core::VolumeCommands::Create(create_args) => {
+                client_create_volume(create_args, stream).await
+            }
+            crate::core::VolumeCommands::Ls => client_list_volumes(stream).await,
+            crate::core::VolumeCommands::Inspect(name_args) => {
+                client_inspect_volume(name_args, stream).await
+            }
+            crate::core::VolumeCommands::Rm(rm_args) => {
+                client_remove_volumes(rm_args, stream).await
+            }
+        },
+        Commands::Label(label_commands) => match label_commands {
+            crate::core::LabelCommands::Add(add_args) => client_add_labels(add_args, stream).await,
+            crate::core::LabelCommands::Rm(rm_args) => client_remove_labels(rm_args, stream).await,
+        },
+        Commands::System(system_commands) => match system_commands {
+            crate::core::SystemCommands::Prune(prune_args) => {
+                client_prune_system(prune_args, stream).await
+            }
         },
     }
 
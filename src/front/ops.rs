@@ -11,19 +11,59 @@ pub async fn client_run_container(args: RunArgs, stream: UnixStream) {
     client_do_run(args.detach, stream).await;
 }
 
-pub async fn client_start_container(args: StartArgs, stream: UnixStream) {
-    client_do_run(args.detach, stream).await;
+pub async fn client_start_container(args: StartArgs, mut stream: UnixStream) {
+    if let ([_], []) = (args.names.as_slice(), args.filters.as_slice()) {
+        client_do_run(args.detach, stream).await;
+        return;
+    }
+
+    // Several containers are started detached, with a result for each.
+    client_recv_results(&mut stream).await;
 }
 
 pub async fn client_exec_container(_args: ExecArgs, stream: UnixStream) {
     client_do_run(false, stream).await;
 }
 
-pub async fn client_stop_container(args: StopArgs, mut stream: UnixStream) {
+pub async fn client_stop_container(_args: StopArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_pause_container(args: PauseArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to pause container {}, due to: {e}", args.name),
+            _ => unreachable!(),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_inspect_container(args: InspectArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to inspect container {}, due to: {e}", args.name),
+            _ => unreachable!(),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_kill_container(_args: KillArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_unpause_container(args: UnpauseArgs, mut stream: UnixStream) {
     match Msg::recv_from(&mut stream).await {
         Ok(msg) => match msg {
             Msg::OkContent(cont) => println!("{cont}"),
-            Msg::Err(e) => eprintln!("Failed to stop container {}, due to: {e}", args.name),
+            Msg::Err(e) => eprintln!("Failed to unpause container {}, due to: {e}", args.name),
             _ => unreachable!(),
         },
         Err(e) => {
@@ -62,10 +102,14 @@ pub async fn client_show_logs(args: LogsArgs, mut stream: UnixStream) {
 }
 
 pub async fn client_remove_container(_args: RMArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_commit_container(_args: CommitArgs, mut stream: UnixStream) {
     match Msg::recv_from(&mut stream).await {
         Ok(msg) => match msg {
             Msg::OkContent(cont) => println!("{cont}"),
-            Msg::Err(e) => eprintln!("Failed to rm containers, due to: {e}"),
+            Msg::Err(e) => eprintln!("Failed to commit container, due to: {e}"),
             _ => unreachable!(),
         },
         Err(e) => {
@@ -74,11 +118,80 @@ pub async fn client_remove_container(_args: RMArgs, mut stream: UnixStream) {
     }
 }
 
-pub async fn client_commit_container(_args: CommitArgs, mut stream: UnixStream) {
+pub async fn client_list_images(mut stream: UnixStream) {
     match Msg::recv_from(&mut stream).await {
         Ok(msg) => match msg {
             Msg::OkContent(cont) => println!("{cont}"),
-            Msg::Err(e) => eprintln!("Failed to commit container, due to: {e}"),
+            Msg::Err(e) => eprintln!("Failed to list images, due to: {e}"),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_remove_images(_args: RmiArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_tag_image(args: TagArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to tag image {}, due to: {e}", args.source),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_import_image(args: ImportArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to import image {}, due to: {e}", args.path),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_load_image(args: ImageLoadArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to load images from {}, due to: {e}", args.input),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_save_image(args: ImageSaveArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to save images to {}, due to: {e}", args.output),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_update_container(args: UpdateArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to update container {}, due to: {e}", args.name),
             _ => unreachable!(),
         },
         Err(e) => {
@@ -150,3 +263,169 @@ pub async fn client_create_network(args: crate::core::NetCreateArgs, mut stream:
         }
     }
 }
+
+pub async fn client_add_labels(args: LabelAddArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to label container {}, due to: {e}", args.name),
+            _ => unreachable!(),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_remove_labels(args: LabelRmArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to unlabel container {}, due to: {e}", args.name),
+            _ => unreachable!(),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_list_networks(mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to list networks, due to: {e}"),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_inspect_network(args: crate::core::NetNameArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to inspect network {}, due to: {e}", args.name),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_remove_network(args: crate::core::NetNameArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to rm network {}, due to: {e}", args.name),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_connect_network(args: crate::core::NetConnectArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!(
+                "Failed to connect container {} to network {}, due to: {e}",
+                args.container, args.network
+            ),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_disconnect_network(args: crate::core::NetConnectArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!(
+                "Failed to disconnect container {} from network {}, due to: {e}",
+                args.container, args.network
+            ),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_create_volume(_args: VolumeCreateArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to create volume, due to: {e}"),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_list_volumes(mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to list volumes, due to: {e}"),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_inspect_volume(args: VolumeNameArgs, mut stream: UnixStream) {
+    match Msg::recv_from(&mut stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("Failed to inspect volume {}, due to: {e}", args.name),
+            _ => eprintln!("Unexpected response from daemon"),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
+
+pub async fn client_remove_volumes(_args: VolumeRmArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_prune_containers(_args: PruneArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_prune_networks(_args: PruneArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+pub async fn client_prune_system(_args: PruneArgs, mut stream: UnixStream) {
+    client_recv_results(&mut stream).await;
+}
+
+/// Print the results of an operation on several containers or resources.
+async fn client_recv_results(stream: &mut UnixStream) {
+    match Msg::recv_from(stream).await {
+        Ok(msg) => match msg {
+            Msg::OkContent(cont) => println!("{cont}"),
+            Msg::Err(e) => eprintln!("{e}"),
+            _ => unreachable!(),
+        },
+        Err(e) => {
+            eprintln!("Failed to recv msg from daemon: {e}");
+        }
+    }
+}
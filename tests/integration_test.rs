@@ -50,7 +50,10 @@ async fn test_client_server_communication() {
 
     // Send a PS command
     let cli = CLI {
-        command: Commands::PS(PSArgs { all: false }),
+        command: Commands::PS(PSArgs {
+            all: false,
+            ..Default::default()
+        }),
     };
 
     Msg::Req(cli).send_to(&mut client_stream).await.unwrap();
@@ -160,7 +163,10 @@ async fn test_error_handling() {
     let mut client_stream = UnixStream::connect(&socket_path).await.unwrap();
 
     let cli = CLI {
-        command: Commands::PS(PSArgs { all: false }),
+        command: Commands::PS(PSArgs {
+            all: false,
+            ..Default::default()
+        }),
     };
 
     Msg::Req(cli).send_to(&mut client_stream).await.unwrap();